@@ -1,6 +1,8 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
+use parse_display_derive::{Display, FromStr};
 use pathfinding::matrix::Matrix;
+use std::collections::{BTreeMap, HashSet};
 
 const ROLL_OF_PAPER: char = '@';
 
@@ -10,69 +12,321 @@ pub struct Cell {
     num_neighbours: usize,
 }
 
+const EMPTY_CELL: Cell = Cell {
+    kind: '.',
+    num_neighbours: 0,
+};
+
+#[derive(Display, FromStr, Copy, Clone, Default, Eq, PartialEq, Debug)]
+#[display(style = "lowercase")]
+pub enum Topology {
+    /// Square grid without wrap-around, neighbours include diagonals
+    #[default]
+    Bounded,
+    /// Square grid wrapping around at both axes, neighbours include diagonals
+    Torus,
+    /// Hexagonal grid in axial coordinates, columns are `q` and rows are `r`
+    Hex,
+}
+
+impl Topology {
+    #[must_use]
+    pub fn neighbours(
+        self,
+        rows: usize,
+        columns: usize,
+        pos: (usize, usize),
+    ) -> Vec<(usize, usize)> {
+        const SQUARE: [(isize, isize); 8] = [
+            (-1, -1),
+            (-1, 0),
+            (-1, 1),
+            (0, -1),
+            (0, 1),
+            (1, -1),
+            (1, 0),
+            (1, 1),
+        ];
+        const HEX: [(isize, isize); 6] = [(0, 1), (0, -1), (1, 0), (-1, 0), (-1, 1), (1, -1)];
+
+        let (row, col) = (pos.0.cast_signed(), pos.1.cast_signed());
+        let (num_rows, num_cols) = (rows.cast_signed(), columns.cast_signed());
+
+        let deltas: &[(isize, isize)] = match self {
+            Self::Bounded | Self::Torus => &SQUARE,
+            Self::Hex => &HEX,
+        };
+
+        deltas
+            .iter()
+            .filter_map(|&(dr, dc)| {
+                let (r, c) = (row + dr, col + dc);
+                match self {
+                    Self::Torus => Some((r.rem_euclid(num_rows), c.rem_euclid(num_cols))),
+                    _ if (0..num_rows).contains(&r) && (0..num_cols).contains(&c) => Some((r, c)),
+                    _ => None,
+                }
+            })
+            .map(|(r, c)| (r.cast_unsigned(), c.cast_unsigned()))
+            // tiny tori wrap onto the same cell more than once (or onto itself)
+            .filter(|&p| p != pos)
+            .unique()
+            .collect_vec()
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Cells {
+    Dense(Matrix<Cell>),
+    /// Neighbour counts of the rolls only, ordered row-major like the dense matrix
+    Sparse(BTreeMap<(usize, usize), usize>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Grid {
+    rows: usize,
+    columns: usize,
+    cells: Cells,
+    topology: Topology,
+}
+
+impl Grid {
+    fn num_accessible(&self) -> usize {
+        match &self.cells {
+            Cells::Dense(cells) => cells.values().filter(|c| is_accessible(c)).count(),
+            Cells::Sparse(rolls) => rolls.values().filter(|&&n| n < 4).count(),
+        }
+    }
+
+    fn remove_wave(&mut self) -> usize {
+        let mut removed = 0;
+
+        let (rows, columns, topology) = (self.rows, self.columns, self.topology);
+
+        match &mut self.cells {
+            Cells::Dense(cells) => {
+                for pos in cells.keys() {
+                    let c = &mut cells[pos];
+                    if !is_accessible(c) {
+                        continue;
+                    }
+
+                    removed += 1;
+                    c.kind = '.';
+
+                    for n in topology.neighbours(rows, columns, pos) {
+                        cells[n].num_neighbours -= 1;
+                    }
+                }
+            }
+            Cells::Sparse(rolls) => {
+                for pos in rolls.keys().copied().collect_vec() {
+                    if rolls[&pos] >= 4 {
+                        continue;
+                    }
+
+                    removed += 1;
+                    rolls.remove(&pos);
+
+                    for n in topology.neighbours(rows, columns, pos) {
+                        if let Some(num_neighbours) = rolls.get_mut(&n) {
+                            *num_neighbours -= 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        removed
+    }
+
+    fn to_matrix(&self) -> Matrix<Cell> {
+        match &self.cells {
+            Cells::Dense(cells) => cells.clone(),
+            Cells::Sparse(rolls) => {
+                let mut cells = Matrix::new(self.rows, self.columns, EMPTY_CELL);
+                for (&pos, &num_neighbours) in rolls {
+                    cells[pos] = Cell {
+                        kind: ROLL_OF_PAPER,
+                        num_neighbours,
+                    };
+                }
+
+                cells
+            }
+        }
+    }
+}
+
+/// Optional header lines select the topology and storage of the grid.
+///
+/// `topology: <bounded|torus|hex>` picks the neighbourhood, `sparse: <rows>x<columns>` switches
+/// to a sparse layout where the remaining lines list the `row,column` positions of the rolls.
 #[aoc_generator(day04)]
-pub fn generate(s: &str) -> Option<Matrix<Cell>> {
+#[must_use]
+pub fn generate(s: &str) -> Option<Grid> {
+    let mut topology = Topology::default();
+    let mut sparse_size = None;
+
+    let mut lines = s.lines().peekable();
+    while let Some(line) = lines.peek() {
+        if let Some(value) = line.strip_prefix("topology:") {
+            topology = value.trim().parse::<Topology>().ok()?;
+        } else if let Some(value) = line.strip_prefix("sparse:") {
+            let (rows, columns) = value.trim().split_once('x')?;
+            sparse_size = Some((rows.parse::<usize>().ok()?, columns.parse::<usize>().ok()?));
+        } else {
+            break;
+        }
+
+        lines.next();
+    }
+
+    let lines = lines.collect_vec();
+    match sparse_size {
+        Some((rows, columns)) => generate_sparse(&lines, rows, columns, topology),
+        None => generate_dense(&lines, topology),
+    }
+}
+
+fn generate_dense(lines: &[&str], topology: Topology) -> Option<Grid> {
     let matrix =
-        Matrix::from_rows(s.lines().map(|l| l.chars().collect_vec()).collect_vec()).ok()?;
+        Matrix::from_rows(lines.iter().map(|l| l.chars().collect_vec()).collect_vec()).ok()?;
 
-    let mut cells = Matrix::new(
-        matrix.rows,
-        matrix.columns,
-        Cell {
-            kind: '.',
-            num_neighbours: 0,
-        },
-    );
+    let mut cells = Matrix::new(matrix.rows, matrix.columns, EMPTY_CELL);
 
     for (pos, c) in matrix.items() {
-        let num_neighbours = matrix
-            .neighbours(pos, true)
-            .filter(|p| matrix[p] == ROLL_OF_PAPER)
+        let num_neighbours = topology
+            .neighbours(matrix.rows, matrix.columns, pos)
+            .iter()
+            .filter(|&p| matrix[p] == ROLL_OF_PAPER)
             .count();
         let cell_to_update = &mut cells[pos];
         cell_to_update.kind = *c;
         cell_to_update.num_neighbours = num_neighbours;
     }
 
-    Some(cells)
+    Some(Grid {
+        rows: matrix.rows,
+        columns: matrix.columns,
+        cells: Cells::Dense(cells),
+        topology,
+    })
+}
+
+fn generate_sparse(
+    lines: &[&str],
+    rows: usize,
+    columns: usize,
+    topology: Topology,
+) -> Option<Grid> {
+    let mut positions = HashSet::new();
+    for line in lines.iter().filter(|l| !l.is_empty()) {
+        let (row, col) = line.split_once(',')?;
+        let pos = (
+            row.trim().parse::<usize>().ok()?,
+            col.trim().parse::<usize>().ok()?,
+        );
+        if pos.0 >= rows || pos.1 >= columns {
+            return None;
+        }
+
+        positions.insert(pos);
+    }
+
+    let rolls = positions
+        .iter()
+        .map(|&pos| {
+            let num_neighbours = topology
+                .neighbours(rows, columns, pos)
+                .iter()
+                .filter(|p| positions.contains(p))
+                .count();
+            (pos, num_neighbours)
+        })
+        .collect();
+
+    Some(Grid {
+        rows,
+        columns,
+        cells: Cells::Sparse(rolls),
+        topology,
+    })
 }
 
 #[aoc(day04, part1)]
-pub fn part1(inp: &Matrix<Cell>) -> usize {
-    inp.items().filter(|&(_, c)| is_accessible(c)).count()
+#[must_use]
+pub fn part1(inp: &Grid) -> usize {
+    inp.num_accessible()
 }
 
 #[aoc(day04, part2)]
-pub fn part2(inp: &Matrix<Cell>) -> usize {
+#[must_use]
+pub fn part2(inp: &Grid) -> usize {
     let mut removed = 0;
 
     let mut inp = inp.clone();
 
     loop {
-        let mut had_changes = false;
+        let removed_in_wave = inp.remove_wave();
+        if removed_in_wave == 0 {
+            break;
+        }
 
-        for pos in inp.keys() {
-            let c = &mut inp[pos];
-            if !is_accessible(c) {
-                continue;
-            }
+        removed += removed_in_wave;
+    }
 
-            removed += 1;
-            c.kind = '.';
+    removed
+}
 
-            for n in inp.neighbours(pos, true) {
-                inp[n].num_neighbours -= 1;
-            }
+/// Grid states of the removal process: the initial grid followed by the grid after each wave.
+/// Sparse grids are rasterized, so this is only suitable for grids that fit into memory densely.
+#[must_use]
+pub fn removal_frames(inp: &Grid) -> Vec<Matrix<Cell>> {
+    let mut frames = vec![inp.to_matrix()];
 
-            had_changes = true;
-        }
+    let mut inp = inp.clone();
+    while inp.remove_wave() > 0 {
+        frames.push(inp.to_matrix());
+    }
 
-        if !had_changes {
-            break;
-        }
+    frames
+}
+
+#[must_use]
+pub fn render_ascii(frame: &Matrix<Cell>) -> String {
+    frame
+        .iter()
+        .map(|row| row.iter().map(|c| c.kind).collect::<String>())
+        .join("\n")
+}
+
+/// Binary PPM (P6) image with one pixel per cell. Rolls are coloured by their neighbour count
+/// (green for freely accessible, through to red for fully surrounded), empty cells are black.
+#[must_use]
+pub fn render_ppm(frame: &Matrix<Cell>) -> Vec<u8> {
+    let mut ppm = format!("P6\n{} {}\n255\n", frame.columns, frame.rows).into_bytes();
+
+    for cell in frame.values() {
+        ppm.extend_from_slice(&cell_colour(cell));
     }
 
-    removed
+    ppm
+}
+
+const fn cell_colour(cell: &Cell) -> [u8; 3] {
+    if cell.kind != ROLL_OF_PAPER {
+        return [0, 0, 0];
+    }
+
+    // neighbours range from 0 to 8 (6 on hex grids), blend from green to red
+    let n = if cell.num_neighbours > 8 {
+        8
+    } else {
+        cell.num_neighbours as u8
+    };
+
+    [n * 31, 255 - n * 31, 0]
 }
 
 const fn is_accessible(cell: &Cell) -> bool {
@@ -107,4 +361,93 @@ mod tests {
         let res = part2(&data);
         assert_eq!(res, 43);
     }
+
+    #[test]
+    fn test_removal_frames() {
+        let data = generate(TEST_INPUT).expect("valid input data");
+        let frames = removal_frames(&data);
+
+        assert_eq!(render_ascii(&frames[0]), TEST_INPUT);
+
+        let rolls =
+            |frame: &Matrix<Cell>| frame.values().filter(|c| c.kind == ROLL_OF_PAPER).count();
+        let removed = rolls(&frames[0]) - rolls(frames.last().expect("final frame"));
+        assert_eq!(removed, 43);
+
+        let ppm = render_ppm(&frames[0]);
+        let header = b"P6\n10 10\n255\n";
+        assert!(ppm.starts_with(header));
+        assert_eq!(ppm.len(), header.len() + 10 * 10 * 3);
+    }
+
+    #[test]
+    fn test_torus() {
+        // every cell of a 3x3 torus neighbours all other eight
+        let data = generate("topology: torus\n@@@\n@@@\n@@@").expect("valid input data");
+        assert_eq!(part1(&data), 0);
+        assert_eq!(part2(&data), 0);
+
+        // the bottom right roll wraps around to the top left block, which then has four
+        // neighbours until the rest of the block is removed
+        let grid = "@@..\n@@..\n....\n...@";
+        let data = generate(&format!("topology: torus\n{grid}")).expect("valid input data");
+        assert_eq!(part1(&data), 4);
+        assert_eq!(part2(&data), 5);
+
+        let bounded = generate(&format!("topology: bounded\n{grid}")).expect("valid input data");
+        assert_eq!(part1(&bounded), 5);
+    }
+
+    #[test]
+    fn test_hex() {
+        // in a 3x3 rhombus, two corners have two and two have three neighbours
+        let data = generate("topology: hex\n@@@\n@@@\n@@@").expect("valid input data");
+        assert_eq!(part1(&data), 4);
+        assert_eq!(part2(&data), 9);
+
+        // a 4x4 rhombus erodes from its corners inwards until nothing is left
+        let data = generate("topology: hex\n@@@@\n@@@@\n@@@@\n@@@@").expect("valid input data");
+        assert_eq!(part1(&data), 4);
+        assert_eq!(part2(&data), 16);
+    }
+
+    #[test]
+    fn test_sparse() {
+        let coordinates = TEST_INPUT
+            .lines()
+            .enumerate()
+            .flat_map(|(row, l)| {
+                l.chars()
+                    .positions(|c| c == ROLL_OF_PAPER)
+                    .map(move |col| format!("{row},{col}"))
+            })
+            .join("\n");
+
+        let data = generate(&format!("sparse: 10x10\n{coordinates}")).expect("valid input data");
+        assert_eq!(part1(&data), 13);
+        assert_eq!(part2(&data), 43);
+        assert_eq!(render_ascii(&removal_frames(&data)[0]), TEST_INPUT);
+
+        let huge = "sparse: 1000000000x1000000000\n\
+                    0,0\n0,1\n1,0\n1,1\n\
+                    999999999,999999999";
+        let data = generate(huge).expect("valid input data");
+        assert_eq!(part1(&data), 5);
+        assert_eq!(part2(&data), 5);
+
+        // a scanner dump without any rolls has nothing after its header
+        let data = generate("topology: torus\nsparse: 3x3").expect("valid input data");
+        assert_eq!(
+            (data.rows, data.columns, data.topology),
+            (3, 3, Topology::Torus)
+        );
+        assert!(matches!(&data.cells, Cells::Sparse(rolls) if rolls.is_empty()));
+        assert_eq!(part2(&data), 0);
+
+        let data = generate("topology: torus").expect("valid input data");
+        assert_eq!(
+            (data.rows, data.columns, data.topology),
+            (0, 0, Topology::Torus)
+        );
+    }
 }
@@ -1,60 +1,268 @@
+use crate::range_set::RangeSet;
 use aoc_runner_derive::{aoc, aoc_generator};
+use itertools::Itertools;
 use rayon::prelude::*;
+use std::io::{self, BufRead};
 
 pub struct Input {
-    ranges: Vec<(usize, usize)>,
-    ids: Vec<usize>,
+    ranges: Vec<(u64, u64)>,
+    fresh: RangeSet,
+    ids: Vec<u64>,
+    diagnostics: Vec<String>,
+}
+
+impl Input {
+    /// Repairs applied while parsing, e.g. reversed ranges that were swapped.
+    #[must_use]
+    pub fn diagnostics(&self) -> &[String] {
+        &self.diagnostics
+    }
 }
 
 #[aoc_generator(day05)]
+#[must_use]
 pub fn generate(s: &str) -> Option<Input> {
     let (range_lines, id_lines) = s.split_once("\n\n")?;
 
-    let mut ranges = vec![];
-    for line in range_lines.lines() {
-        let (from, to) = line.split_once('-')?;
-        ranges.push((from.parse::<usize>().ok()?, to.parse::<usize>().ok()?));
-    }
+    let mut diagnostics = vec![];
+    let ranges = parse_ranges(range_lines, &mut diagnostics)?;
 
     let mut ids = vec![];
     for line in id_lines.lines() {
-        ids.push(line.parse::<usize>().ok()?);
+        ids.push(line.parse::<u64>().ok()?);
     }
 
-    Some(Input { ranges, ids })
+    Some(Input {
+        fresh: ranges.iter().copied().collect(),
+        ranges,
+        ids,
+        diagnostics,
+    })
+}
+
+fn parse_ranges(range_lines: &str, diagnostics: &mut Vec<String>) -> Option<Vec<(u64, u64)>> {
+    let mut ranges = vec![];
+    for (line_idx, line) in range_lines.lines().enumerate() {
+        let (from, to) = line.split_once('-')?;
+        let (from, to) = (from.parse::<u64>().ok()?, to.parse::<u64>().ok()?);
+
+        if from > to {
+            diagnostics.push(format!(
+                "line {}: reversed range {from}-{to}, treated as {to}-{from}",
+                line_idx + 1
+            ));
+            ranges.push((to, from));
+        } else {
+            ranges.push((from, to));
+        }
+    }
+
+    Some(ranges)
 }
 
 #[aoc(day05, part1)]
+#[must_use]
 pub fn part1(inp: &Input) -> usize {
     inp.ids
         .par_iter()
-        .map(|&id| {
-            let is_fresh = inp.ranges.iter().any(|&(from, to)| id >= from && id <= to);
-            usize::from(is_fresh)
-        })
-        .sum()
+        .filter(|&&id| inp.fresh.contains(id))
+        .count()
 }
 
 #[aoc(day05, part2)]
-pub fn part2(inp: &Input) -> usize {
-    let mut ranges = inp.ranges.clone();
-    ranges.sort_by_key(|(from, _)| *from);
+#[must_use]
+pub fn part2(inp: &Input) -> u128 {
+    inp.fresh.len()
+}
 
-    let mut count = 0;
-    let mut largest_fresh = 0;
+/// Range index built once and queried for IDs read from a stream, so that arbitrarily large ID
+/// dumps never have to be held in memory.
+pub struct Classifier {
+    fresh: RangeSet,
+    diagnostics: Vec<String>,
+}
 
-    for (from, to) in ranges {
-        let diff = if from > largest_fresh {
-            Some(to - from + 1)
-        } else {
-            to.checked_sub(largest_fresh)
-        };
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug)]
+pub struct Tally {
+    pub fresh: usize,
+    pub spoiled: usize,
+}
+
+impl Classifier {
+    /// Builds the index from the range block of the input (without the IDs).
+    #[must_use]
+    pub fn from_ranges(range_lines: &str) -> Option<Self> {
+        let mut diagnostics = vec![];
+        let ranges = parse_ranges(range_lines, &mut diagnostics)?;
+        Some(Self {
+            fresh: ranges.into_iter().collect(),
+            diagnostics,
+        })
+    }
+
+    /// Repairs applied while parsing the ranges, e.g. reversed ranges that were swapped.
+    #[must_use]
+    pub fn diagnostics(&self) -> &[String] {
+        &self.diagnostics
+    }
+
+    #[must_use]
+    pub fn is_fresh(&self, id: u64) -> bool {
+        self.fresh.contains(id)
+    }
+
+    /// Lazily classifies one ID per line of `reader`, yielding whether it is fresh.
+    /// Blank lines are skipped, anything else that is not a number is yielded as an error.
+    pub fn classify<R: BufRead>(&self, reader: R) -> impl Iterator<Item = io::Result<(u64, bool)>> {
+        reader
+            .lines()
+            .filter_ok(|line| !line.trim().is_empty())
+            .map(|line| {
+                let line = line?;
+                let id = line
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                Ok((id, self.is_fresh(id)))
+            })
+    }
+
+    /// Running totals over all IDs of `reader`.
+    ///
+    /// # Errors
+    ///
+    /// Fails on read errors and lines that are not a valid ID.
+    pub fn tally<R: BufRead>(&self, reader: R) -> io::Result<Tally> {
+        self.classify(reader)
+            .try_fold(Tally::default(), |mut tally, res| {
+                if res?.1 {
+                    tally.fresh += 1;
+                } else {
+                    tally.spoiled += 1;
+                }
+
+                Ok(tally)
+            })
+    }
+}
+
+/// Static interval tree: intervals sorted by start form an implicit balanced search tree, each node
+/// knowing the largest end in its subtree.
+struct IntervalTree {
+    // (from, to, index into the original ranges)
+    intervals: Vec<(u64, u64, usize)>,
+    max_to: Vec<u64>,
+}
+
+impl IntervalTree {
+    fn new(ranges: &[(u64, u64)]) -> Self {
+        let intervals = ranges
+            .iter()
+            .enumerate()
+            .map(|(idx, &(from, to))| (from, to, idx))
+            .sorted()
+            .collect_vec();
+
+        let mut max_to = vec![0; intervals.len()];
+        Self::build(&intervals, &mut max_to, 0, intervals.len());
+
+        Self { intervals, max_to }
+    }
+
+    fn build(intervals: &[(u64, u64, usize)], max_to: &mut [u64], lo: usize, hi: usize) -> u64 {
+        if lo >= hi {
+            return 0;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        let left = Self::build(intervals, max_to, lo, mid);
+        let right = Self::build(intervals, max_to, mid + 1, hi);
 
-        count += diff.unwrap_or_default();
-        largest_fresh = diff.map_or(largest_fresh, |_| to);
+        max_to[mid] = intervals[mid].1.max(left).max(right);
+        max_to[mid]
     }
 
-    count
+    /// Indices of all intervals containing `value`.
+    fn stab(&self, value: u64) -> Vec<usize> {
+        let mut res = vec![];
+        self.stab_rec(value, 0, self.intervals.len(), &mut res);
+        res.sort_unstable();
+        res
+    }
+
+    fn stab_rec(&self, value: u64, lo: usize, hi: usize, res: &mut Vec<usize>) {
+        if lo >= hi {
+            return;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        if self.max_to[mid] < value {
+            return;
+        }
+
+        self.stab_rec(value, lo, mid, res);
+
+        let (from, to, idx) = self.intervals[mid];
+        if from <= value {
+            if to >= value {
+                res.push(idx);
+            }
+
+            self.stab_rec(value, mid + 1, hi, res);
+        }
+    }
+}
+
+pub struct Attribution {
+    matches: Vec<(u64, Vec<usize>)>,
+    hits: Vec<usize>,
+}
+
+impl Attribution {
+    /// Every ID in input order with the input lines of all ranges containing it.
+    #[must_use]
+    pub fn matches(&self) -> &[(u64, Vec<usize>)] {
+        &self.matches
+    }
+
+    /// Number of IDs contained in the range on the given input line, `None` if there is no range
+    /// on that line.
+    #[must_use]
+    pub fn hits(&self, line: usize) -> Option<usize> {
+        self.hits.get(line.checked_sub(1)?).copied()
+    }
+
+    /// Input lines of ranges that contain none of the IDs.
+    #[must_use]
+    pub fn dead_ranges(&self) -> Vec<usize> {
+        self.hits
+            .iter()
+            .positions(|&h| h == 0)
+            .map(|idx| idx + 1)
+            .collect_vec()
+    }
+}
+
+/// Attributes each ID to the ranges (by 1-based input line) that make it fresh.
+#[must_use]
+pub fn attribute(inp: &Input) -> Attribution {
+    let tree = IntervalTree::new(&inp.ranges);
+
+    let matches = inp
+        .ids
+        .par_iter()
+        .map(|&id| {
+            let lines = tree.stab(id).iter().map(|idx| idx + 1).collect_vec();
+            (id, lines)
+        })
+        .collect::<Vec<_>>();
+
+    let mut hits = vec![0; inp.ranges.len()];
+    for line in matches.iter().flat_map(|(_, lines)| lines) {
+        hits[line - 1] += 1;
+    }
+
+    Attribution { matches, hits }
 }
 
 #[cfg(test)]
@@ -86,4 +294,81 @@ mod tests {
         let res = part2(&data);
         assert_eq!(res, 14);
     }
+
+    #[test]
+    fn test_attribution() {
+        let data = generate(TEST_INPUT).expect("valid test input");
+        let res = attribute(&data);
+
+        let fresh = res
+            .matches()
+            .iter()
+            .filter(|(_, lines)| !lines.is_empty())
+            .collect_vec();
+        assert_eq!(fresh, [&(5, vec![1]), &(11, vec![2]), &(17, vec![3, 4])]);
+        assert_eq!(
+            (0..=5).map(|l| res.hits(l)).collect_vec(),
+            [None, Some(1), Some(1), Some(1), Some(1), None]
+        );
+        assert!(res.dead_ranges().is_empty());
+
+        let data = generate("1-10\n2-3\n5-9\n20-30\n\n4\n5\n6").expect("valid test input");
+        let res = attribute(&data);
+        assert_eq!(res.hits(1), Some(3));
+        assert_eq!(res.hits(3), Some(2));
+        assert_eq!(res.dead_ranges(), [2, 4]);
+    }
+
+    #[test]
+    fn test_streaming() {
+        let (ranges, ids) = TEST_INPUT.split_once("\n\n").expect("valid test input");
+        let classifier = Classifier::from_ranges(ranges).expect("valid ranges");
+
+        let classified = classifier
+            .classify(ids.as_bytes())
+            .collect::<io::Result<Vec<_>>>()
+            .expect("valid IDs");
+        assert_eq!(
+            classified,
+            [
+                (1, false),
+                (5, true),
+                (8, false),
+                (11, true),
+                (17, true),
+                (32, false)
+            ]
+        );
+
+        let tally = classifier.tally(ids.as_bytes()).expect("valid IDs");
+        assert_eq!(
+            tally,
+            Tally {
+                fresh: 3,
+                spoiled: 3
+            }
+        );
+
+        assert!(classifier.tally(&b"1\nfoo\n"[..]).is_err());
+        assert!(classifier.diagnostics().is_empty());
+
+        let classifier = Classifier::from_ranges("3-5\n20-16").expect("valid ranges");
+        assert!(classifier.is_fresh(18));
+        assert_eq!(
+            classifier.diagnostics(),
+            ["line 2: reversed range 20-16, treated as 16-20"]
+        );
+    }
+
+    #[test]
+    fn test_full_domain() {
+        let data = generate("20-16\n0-18446744073709551615\n\n18446744073709551615")
+            .expect("valid test input");
+        assert_eq!(part1(&data), 1);
+        assert_eq!(part2(&data), 1 << 64);
+        assert_eq!(
+            data.diagnostics(),
+            ["line 1: reversed range 20-16, treated as 16-20"]
+        );
+    }
 }
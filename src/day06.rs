@@ -1,74 +1,473 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
+use num::bigint::Sign;
+use num::{BigInt, Integer, ToPrimitive};
+use parse_display_derive::{Display, FromStr};
 use pathfinding::prelude::Matrix;
+use std::fmt;
+use std::ops::Add;
 
-#[aoc_generator(day06)]
-pub fn generate(s: &str) -> Option<Matrix<char>> {
-    Matrix::from_rows(
-        s.lines()
-            .filter(|l| !l.is_empty())
-            .map(|l| l.chars().collect_vec())
-            .collect_vec(),
-    )
-    .ok()
+#[derive(Display, FromStr, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Operator {
+    #[display("+")]
+    Add,
+    #[display("-")]
+    Sub,
+    #[display("*")]
+    Mul,
+    #[display("/")]
+    Div,
+    #[display("%")]
+    Rem,
+    #[display("^")]
+    Pow,
+    #[display("min")]
+    Min,
+    #[display("max")]
+    Max,
 }
 
-#[aoc(day06, part1)]
-pub fn part1(inp: &Matrix<char>) -> usize {
-    cephalopod_math(inp, inp.rows - 1, |col, operands, _, operation| {
-        collect_operands(col, operation, |i, c| operands[i].push(c));
-    })
+/// Largest result of `^` in bits, so that untrusted worksheets cannot exhaust memory.
+const MAX_POW_BITS: u64 = 1 << 20;
+
+impl Operator {
+    /// Uses checked machine arithmetic and only falls back to big integers on overflow.
+    fn apply(self, lhs: &Value, rhs: &Value) -> Option<Value> {
+        if let (&Value::Small(l), &Value::Small(r)) = (lhs, rhs)
+            && let Some(res) = self.apply_small(l, r)
+        {
+            return Some(Value::Small(res));
+        }
+
+        self.apply_big(&lhs.to_big(), &rhs.to_big())
+            .map(Value::from_big)
+    }
+
+    fn apply_small(self, lhs: isize, rhs: isize) -> Option<isize> {
+        match self {
+            Self::Add => lhs.checked_add(rhs),
+            Self::Sub => lhs.checked_sub(rhs),
+            Self::Mul => lhs.checked_mul(rhs),
+            Self::Div => lhs.checked_div(rhs),
+            Self::Rem => lhs.checked_rem(rhs),
+            Self::Pow => lhs.checked_pow(u32::try_from(rhs).ok()?),
+            Self::Min => Some(lhs.min(rhs)),
+            Self::Max => Some(lhs.max(rhs)),
+        }
+    }
+
+    fn apply_big(self, lhs: &BigInt, rhs: &BigInt) -> Option<BigInt> {
+        let is_zero = *rhs == BigInt::ZERO;
+        match self {
+            Self::Add => Some(lhs + rhs),
+            Self::Sub => Some(lhs - rhs),
+            Self::Mul => Some(lhs * rhs),
+            Self::Div if !is_zero => Some(lhs / rhs),
+            Self::Rem if !is_zero => Some(lhs % rhs),
+            Self::Div | Self::Rem => None,
+            Self::Pow if rhs.sign() == Sign::Minus => None,
+            Self::Pow if lhs.bits() <= 1 => {
+                // 0, 1 and -1 only depend on whether the exponent is zero, even or odd
+                let exp = if is_zero {
+                    0
+                } else if rhs.is_even() {
+                    2
+                } else {
+                    1
+                };
+                Some(lhs.pow(exp))
+            }
+            Self::Pow => {
+                let exp = rhs.to_u32()?;
+                if lhs.bits().saturating_mul(exp.into()) > MAX_POW_BITS {
+                    return None;
+                }
+                Some(lhs.pow(exp))
+            }
+            Self::Min => Some(lhs.min(rhs).clone()),
+            Self::Max => Some(lhs.max(rhs).clone()),
+        }
+    }
 }
 
-#[aoc(day06, part2)]
-pub fn part2(inp: &Matrix<char>) -> usize {
-    cephalopod_math(inp, inp.columns, |col, operands, col_idx, operation| {
-        collect_operands(col, operation, |_, c| operands[col_idx].push(c));
-    })
+/// Result of a problem, kept as a machine integer as long as it fits into one.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Value {
+    Small(isize),
+    Big(BigInt),
 }
 
-fn cephalopod_math<F>(inp: &Matrix<char>, num_ops: usize, collect_operands: F) -> usize
-where
-    F: Fn(&[&char], &mut [String], usize, &mut Option<char>),
-{
-    let mut res = 0;
-    let mut operands = vec![String::new(); num_ops];
-    let mut operation = None;
+impl Value {
+    fn from_big(value: BigInt) -> Self {
+        value.to_isize().map_or(Self::Big(value), Self::Small)
+    }
 
-    for (col_idx, col) in inp.column_iter().enumerate() {
-        collect_operands(&col, &mut operands, col_idx, &mut operation);
+    fn to_big(&self) -> BigInt {
+        match self {
+            Self::Small(value) => BigInt::from(*value),
+            Self::Big(value) => value.clone(),
+        }
+    }
+}
+
+impl Add for Value {
+    type Output = Self;
 
-        if col.iter().all(|it| it.is_ascii_whitespace()) || col_idx == inp.columns - 1 {
-            res += finish_operands(operation, &operands);
+    fn add(self, rhs: Self) -> Self::Output {
+        Operator::Add
+            .apply(&self, &rhs)
+            .expect("addition is always defined")
+    }
+}
 
-            operation = None;
-            operands = vec![String::new(); num_ops];
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Small(value) => write!(f, "{value}"),
+            Self::Big(value) => write!(f, "{value}"),
         }
     }
+}
 
-    res
+/// A single problem of the worksheet: the operator applied to its operands from left to right,
+/// i.e. `a - b - c` evaluates as `(a - b) - c` and `a ^ b ^ c` as `(a ^ b) ^ c`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Problem {
+    operator: Operator,
+    operands: Vec<isize>,
+}
+
+impl Problem {
+    #[must_use]
+    pub const fn new(operator: Operator, operands: Vec<isize>) -> Self {
+        Self { operator, operands }
+    }
+
+    #[must_use]
+    pub const fn operator(&self) -> Operator {
+        self.operator
+    }
+
+    #[must_use]
+    pub fn operands(&self) -> &[isize] {
+        &self.operands
+    }
+
+    /// `None` on division by zero, negative exponents or powers too large to compute.
+    #[must_use]
+    pub fn eval(&self) -> Option<Value> {
+        let (first, rest) = self.operands.split_first()?;
+        rest.iter().try_fold(Value::Small(*first), |acc, &it| {
+            self.operator.apply(&acc, &Value::Small(it))
+        })
+    }
+}
+
+/// Direction the operands of a problem are read in.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Layout {
+    /// One operand per row, read top to bottom (part 1)
+    Rows,
+    /// One operand per column with its digits top to bottom, read right to left (part 2)
+    Columns,
+}
+
+#[derive(Clone, Debug)]
+pub struct Worksheet {
+    grid: Matrix<char>,
+    // column range (end exclusive) of each problem
+    spans: Vec<(usize, usize)>,
+    warnings: Vec<String>,
+}
+
+impl Worksheet {
+    /// Repairs applied to malformed input while parsing.
+    #[must_use]
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
 }
 
-fn collect_operands<F>(column: &[&char], operation: &mut Option<char>, mut append_to_operator: F)
-where
-    F: FnMut(usize, char),
-{
-    for (i, &&c) in column.iter().enumerate() {
-        match c {
-            '*' | '+' => *operation = Some(c),
-            c if c.is_ascii_digit() => append_to_operator(i, c),
-            _ => {}
+const TAB_WIDTH: usize = 8;
+
+/// Tolerates ragged input: tabs are expanded, short lines are padded to the widest one and problems
+/// are delimited by the operator row if a separator column is not completely blank.
+#[aoc_generator(day06)]
+#[must_use]
+pub fn generate(s: &str) -> Option<Worksheet> {
+    let mut warnings = vec![];
+
+    let lines = s
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.is_empty())
+        .map(|(idx, l)| {
+            if l.contains('\t') {
+                warnings.push(format!("line {}: expanded tabs", idx + 1));
+            }
+
+            (idx, expand_tabs(l))
+        })
+        .collect_vec();
+
+    let width = lines.iter().map(|(_, l)| l.len()).max()?;
+
+    let rows = lines
+        .into_iter()
+        .map(|(idx, mut l)| {
+            if l.len() < width {
+                warnings.push(format!(
+                    "line {}: padded {} missing columns",
+                    idx + 1,
+                    width - l.len()
+                ));
+                l.resize(width, ' ');
+            }
+
+            l
+        })
+        .collect_vec();
+
+    let grid = Matrix::from_rows(rows).ok()?;
+    let spans = problem_spans(&grid, &mut warnings)?;
+
+    Some(Worksheet {
+        grid,
+        spans,
+        warnings,
+    })
+}
+
+fn expand_tabs(line: &str) -> Vec<char> {
+    let mut res = vec![];
+    for c in line.chars() {
+        if c == '\t' {
+            res.resize((res.len() / TAB_WIDTH + 1) * TAB_WIDTH, ' ');
+        } else {
+            res.push(c);
         }
     }
+
+    res
+}
+
+/// Each operator in the last row starts a new problem. Problems are separated by the closest blank
+/// column in front of the operator, or the column right in front of it if there is none.
+fn problem_spans(grid: &Matrix<char>, warnings: &mut Vec<String>) -> Option<Vec<(usize, usize)>> {
+    let operator_row = grid.iter().next_back()?;
+    let is_blank = |col: usize| (0..grid.rows).all(|row| grid[(row, col)].is_whitespace());
+
+    let operator_starts = operator_row
+        .iter()
+        .enumerate()
+        .filter(|&(col, c)| {
+            !c.is_whitespace() && (col == 0 || operator_row[col - 1].is_whitespace())
+        })
+        .map(|(col, _)| col)
+        .collect_vec();
+
+    let mut spans = vec![];
+    let mut start = 0;
+
+    for (prev, next) in operator_starts.iter().tuple_windows() {
+        let separator = (prev + 1..*next)
+            .rev()
+            .find(|&col| is_blank(col))
+            .unwrap_or_else(|| {
+                let col = next - 1;
+                let dropped = (0..grid.rows)
+                    .map(|row| grid[(row, col)])
+                    .filter(|c| !c.is_whitespace())
+                    .collect::<String>();
+                warnings.push(format!(
+                    "column {}: separator in front of problem {} is not blank, ignoring '{dropped}'",
+                    col + 1,
+                    spans.len() + 2
+                ));
+                col
+            });
+
+        spans.push((start, separator));
+        start = separator + 1;
+    }
+
+    if !operator_starts.is_empty() {
+        spans.push((start, grid.columns));
+    }
+
+    Some(spans)
+}
+
+#[aoc(day06, part1)]
+#[must_use]
+pub fn part1(inp: &Worksheet) -> Option<Value> {
+    cephalopod_math(inp, Layout::Rows)
+}
+
+#[aoc(day06, part2)]
+#[must_use]
+pub fn part2(inp: &Worksheet) -> Option<Value> {
+    cephalopod_math(inp, Layout::Columns)
 }
 
-fn finish_operands(operation: Option<char>, operands: &[String]) -> usize {
-    let nums = operands.iter().filter_map(|it| it.parse::<usize>().ok());
-    match operation {
-        Some('*') => nums.product(),
-        Some('+') => nums.sum(),
-        _ => panic!("unknown operation"),
+fn cephalopod_math(inp: &Worksheet, layout: Layout) -> Option<Value> {
+    evaluate(inp, layout)?
+        .into_iter()
+        .try_fold(Value::Small(0), |acc, evaluation| {
+            Some(acc + evaluation.result?)
+        })
+}
+
+#[derive(Clone, Debug)]
+pub struct Evaluation {
+    /// Column range (end exclusive) of the problem on the worksheet
+    pub span: (usize, usize),
+    pub problem: Problem,
+    pub result: Option<Value>,
+}
+
+/// Per-problem breakdown of the worksheet, `None` if an operator is unknown.
+#[must_use]
+pub fn evaluate(inp: &Worksheet, layout: Layout) -> Option<Vec<Evaluation>> {
+    let evaluations = inp
+        .spans
+        .iter()
+        .zip(parse_problems(inp, layout)?)
+        .map(|(&span, problem)| Evaluation {
+            span,
+            result: problem.eval(),
+            problem,
+        })
+        .collect_vec();
+
+    Some(evaluations)
+}
+
+/// Parses each problem of the worksheet, `None` if an operator is unknown.
+#[must_use]
+pub fn parse_problems(inp: &Worksheet, layout: Layout) -> Option<Vec<Problem>> {
+    let columns = inp.grid.column_iter().collect_vec();
+
+    inp.spans
+        .iter()
+        .map(|&(start, end)| parse_problem(&columns[start..end], layout))
+        .collect()
+}
+
+fn parse_problem(columns: &[Vec<&char>], layout: Layout) -> Option<Problem> {
+    let operator = columns
+        .iter()
+        .filter_map(|col| col.last())
+        .copied()
+        .collect::<String>()
+        .trim()
+        .parse::<Operator>()
+        .ok()?;
+
+    let digits = |col: &Vec<&char>| {
+        col.iter()
+            .take(col.len() - 1)
+            .filter(|c| c.is_ascii_digit())
+            .copied()
+            .collect::<String>()
+    };
+
+    let operands = match layout {
+        Layout::Rows => {
+            let num_rows = columns.first()?.len() - 1;
+            (0..num_rows)
+                .map(|row| columns.iter().map(|col| col[row]).collect::<String>())
+                .filter(|row| !row.trim().is_empty())
+                .map(|row| row.trim().parse::<isize>().ok())
+                .collect::<Option<Vec<_>>>()?
+        }
+        Layout::Columns => columns
+            .iter()
+            .rev()
+            .map(digits)
+            .filter(|it| !it.is_empty())
+            .map(|it| it.parse::<isize>().ok())
+            .collect::<Option<Vec<_>>>()?,
+    };
+
+    Some(Problem { operator, operands })
+}
+
+/// Renders problems as an aligned worksheet that `generate` reads back in the given layout.
+/// `None` if an operand is negative, as those cannot be written on a worksheet.
+#[must_use]
+pub fn render(problems: &[Problem], layout: Layout) -> Option<String> {
+    let operands = problems
+        .iter()
+        .map(|p| {
+            p.operands
+                .iter()
+                .map(|&it| usize::try_from(it).ok().map(|it| it.to_string()))
+                .collect::<Option<Vec<_>>>()
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let height = match layout {
+        Layout::Rows => operands.iter().map(Vec::len).max(),
+        Layout::Columns => operands.iter().flatten().map(String::len).max(),
     }
+    .unwrap_or_default();
+
+    let blocks = problems
+        .iter()
+        .zip(operands)
+        .map(|(problem, operands)| {
+            let operator = problem.operator.to_string();
+
+            let mut block = match layout {
+                Layout::Rows => {
+                    let width = operands
+                        .iter()
+                        .map(String::len)
+                        .chain(std::iter::once(operator.len()))
+                        .max()
+                        .unwrap_or_default();
+
+                    (0..height)
+                        .map(|row| format!("{:>width$}", operands.get(row).map_or("", |it| it)))
+                        .collect_vec()
+                }
+                Layout::Columns => {
+                    let width = operands.len().max(operator.len());
+
+                    // the first operand is the rightmost column
+                    (0..height)
+                        .map(|row| {
+                            (0..width)
+                                .map(|col| {
+                                    operands
+                                        .get(width - 1 - col)
+                                        .and_then(|it| it.chars().nth(row))
+                                        .unwrap_or(' ')
+                                })
+                                .collect::<String>()
+                        })
+                        .collect_vec()
+                }
+            };
+
+            let width = block.first().map_or(operator.len(), String::len);
+            block.push(format!("{operator:<width$}"));
+            block
+        })
+        .collect_vec();
+
+    let res = (0..=height)
+        .map(|row| blocks.iter().map(|block| &block[row]).join(" "))
+        .join("\n");
+
+    Some(res)
+}
+
+/// Re-renders a worksheet read in layout `from` so that it reads the same problems in layout `to`.
+#[must_use]
+pub fn convert(inp: &Worksheet, from: Layout, to: Layout) -> Option<String> {
+    render(&parse_problems(inp, from)?, to)
 }
 
 #[cfg(test)]
@@ -85,13 +484,130 @@ mod tests {
     fn test_p1() {
         let data = generate(TEST_INPUT).expect("valid input");
         let res = part1(&data);
-        assert_eq!(res, 4_277_556);
+        assert_eq!(res, Some(Value::Small(4_277_556)));
     }
 
     #[test]
     fn test_p2() {
         let data = generate(TEST_INPUT).expect("valid input");
         let res = part2(&data);
-        assert_eq!(res, 3_263_827);
+        assert_eq!(res, Some(Value::Small(3_263_827)));
+    }
+
+    #[test]
+    fn test_operators() {
+        let data = generate(
+            "20 17 2  12 7   9  \n\
+             3  5  10 5  12  4  \n\
+             -  %  ^  /  min max",
+        )
+        .expect("valid input");
+
+        let results = parse_problems(&data, Layout::Rows)
+            .expect("known operators")
+            .iter()
+            .map(Problem::eval)
+            .collect_vec();
+        assert_eq!(
+            results,
+            [17, 2, 1024, 2, 7, 9].map(|it| Some(Value::Small(it)))
+        );
+
+        // columns are read right to left: 03 - 20 and 75 - 13
+        let data = generate("20 17\n03 35\n-  - ").expect("valid input");
+        assert_eq!(part2(&data), Some(Value::Small((3 - 20) + (75 - 13))));
+
+        let data = generate("1\n2\n&").expect("valid input");
+        assert_eq!(part1(&data), None);
+
+        // powers are capped in size instead of exhausting memory
+        let eval = |operands: &[isize]| Problem::new(Operator::Pow, operands.to_vec()).eval();
+        assert_eq!(eval(&[9, 4_000_000_000]), None);
+        assert_eq!(eval(&[-1, 4_000_000_001]), Some(Value::Small(-1)));
+        assert_eq!(eval(&[-1, 5_000_000_000]), Some(Value::Small(1)));
+        assert_eq!(eval(&[1, 5_000_000_000]), Some(Value::Small(1)));
+        assert_eq!(eval(&[0, 5_000_000_000]), Some(Value::Small(0)));
+        assert_eq!(eval(&[0, 0]), Some(Value::Small(1)));
+        assert_eq!(eval(&[1, -1]), None);
+        assert_eq!(
+            eval(&[2, 100]),
+            Some(Value::Big(BigInt::from(1) << 100usize))
+        );
+    }
+
+    #[test]
+    fn test_ragged_lines() {
+        let data = generate(TEST_INPUT).expect("valid input");
+        assert!(data.warnings().is_empty());
+
+        // trailing spaces stripped, a tab instead of spaces and a digit in a separator column
+        let input = [
+            "123 328\t 51 64",
+            " 45 64  387 23",
+            "  6 98 7215 314",
+            "*   +   *   +",
+        ]
+        .join("\n");
+        let data = generate(&input).expect("valid input");
+        assert_eq!(
+            data.warnings(),
+            [
+                "line 1: expanded tabs",
+                "line 1: padded 1 missing columns",
+                "line 2: padded 1 missing columns",
+                "line 4: padded 2 missing columns",
+                "column 8: separator in front of problem 3 is not blank, ignoring '7'",
+            ]
+        );
+        assert_eq!(part1(&data), Some(Value::Small(4_277_556)));
+        assert_eq!(part2(&data), Some(Value::Small(3_263_827)));
+    }
+
+    #[test]
+    fn test_breakdown() {
+        let data = generate(TEST_INPUT).expect("valid input");
+        let evaluations = evaluate(&data, Layout::Rows).expect("known operators");
+
+        let spans = evaluations.iter().map(|it| it.span).collect_vec();
+        assert_eq!(spans, [(0, 3), (4, 7), (8, 11), (12, 15)]);
+
+        let first = &evaluations[0];
+        assert_eq!(first.problem.operator(), Operator::Mul);
+        assert_eq!(first.problem.operands(), [123, 45, 6]);
+        assert_eq!(first.result, Some(Value::Small(33_210)));
+
+        let data = generate("9223372036854775807 2\n2                   3\n*                   -")
+            .expect("valid input");
+        let res = part1(&data).expect("defined result");
+        assert!(matches!(res, Value::Big(_)));
+        assert_eq!(res.to_string(), "18446744073709551613");
+    }
+
+    #[test]
+    fn test_render() {
+        let problems = vec![
+            Problem::new(Operator::Sub, vec![100, 7, 25]),
+            Problem::new(Operator::Max, vec![4, 12]),
+            Problem::new(Operator::Mul, vec![3]),
+        ];
+
+        let rows = render(&problems, Layout::Rows).expect("non-negative operands");
+        assert_eq!(rows, "100   4 3\n  7  12  \n 25      \n-   max *");
+
+        for layout in [Layout::Rows, Layout::Columns] {
+            let text = render(&problems, layout).expect("non-negative operands");
+            let data = generate(&text).expect("valid input");
+            assert_eq!(parse_problems(&data, layout), Some(problems.clone()));
+        }
+
+        let data = generate(TEST_INPUT).expect("valid input");
+        let converted = convert(&data, Layout::Columns, Layout::Rows).expect("valid input");
+        let converted = generate(&converted).expect("valid input");
+        assert_eq!(part1(&converted), part2(&data));
+
+        assert_eq!(
+            render(&[Problem::new(Operator::Add, vec![-1])], Layout::Rows),
+            None
+        );
     }
 }
@@ -1,67 +1,484 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
+use num::{BigUint, ToPrimitive};
 use pathfinding::matrix::Matrix;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
 
 const SPLITTER: char = '^';
+const MIRROR: char = '/';
+const BACK_MIRROR: char = '\\';
+const ABSORBER: char = '#';
+const MERGE: char = 'v';
 
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    const fn delta(self) -> (isize, isize) {
+        match self {
+            Self::Up => (-1, 0),
+            Self::Down => (1, 0),
+            Self::Left => (0, -1),
+            Self::Right => (0, 1),
+        }
+    }
+
+    const fn perpendicular(self) -> [Self; 2] {
+        match self {
+            Self::Up | Self::Down => [Self::Left, Self::Right],
+            Self::Left | Self::Right => [Self::Up, Self::Down],
+        }
+    }
+
+    const fn reflect(self, mirror: char) -> Self {
+        match (mirror, self) {
+            (MIRROR, Self::Down) | (BACK_MIRROR, Self::Up) => Self::Left,
+            (MIRROR, Self::Up) | (BACK_MIRROR, Self::Down) => Self::Right,
+            (MIRROR, Self::Left) | (BACK_MIRROR, Self::Right) => Self::Down,
+            _ => Self::Up,
+        }
+    }
+}
+
+/// A beam occupying a cell while travelling in a direction.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Beam {
+    pos: (usize, usize),
+    dir: Direction,
+}
+
+impl Beam {
+    fn moved(self, matrix: &Matrix<char>, dir: Direction) -> Option<(usize, usize)> {
+        let (dr, dc) = dir.delta();
+        let row = self.pos.0.checked_add_signed(dr)?;
+        let col = self.pos.1.checked_add_signed(dc)?;
+
+        (row < matrix.rows && col < matrix.columns).then_some((row, col))
+    }
+}
+
+enum Next {
+    Beam(Beam),
+    /// The beam leaves the manifold from the given cell
+    Exit(Beam),
+}
+
+#[derive(Clone, Debug)]
+pub struct Manifold {
+    grid: Matrix<char>,
+    sources: Vec<(usize, usize)>,
+}
+
+impl Manifold {
+    #[must_use]
+    pub fn sources(&self) -> &[(usize, usize)] {
+        &self.sources
+    }
+}
+
+/// Beams are emitted downwards from every `S` cell and from the `row,col` positions listed in an
+/// optional `sources:` header line. `None` if there is no source at all.
 #[aoc_generator(day07)]
-pub fn generate(s: &str) -> Option<Matrix<char>> {
-    Matrix::from_rows(s.lines().map(|l| l.chars().collect_vec()).collect_vec()).ok()
+#[must_use]
+pub fn generate(s: &str) -> Option<Manifold> {
+    let mut sources = vec![];
+
+    let s = match s.split_once('\n') {
+        Some((header, rest)) if header.starts_with("sources:") => {
+            let (_, positions) = header.split_once(':')?;
+            for pos in positions.split_ascii_whitespace() {
+                let (row, col) = pos.split_once(',')?;
+                sources.push((row.parse::<usize>().ok()?, col.parse::<usize>().ok()?));
+            }
+
+            rest
+        }
+        _ => s,
+    };
+
+    let grid = Matrix::from_rows(s.lines().map(|l| l.chars().collect_vec()).collect_vec()).ok()?;
+
+    if sources
+        .iter()
+        .any(|&(row, col)| row >= grid.rows || col >= grid.columns)
+    {
+        return None;
+    }
+
+    sources.extend(grid.items().filter(|(_, c)| **c == 'S').map(|(p, _)| p));
+    sources.sort_unstable();
+    sources.dedup();
+
+    (!sources.is_empty()).then_some(Manifold { grid, sources })
 }
 
 #[aoc(day07, part1)]
-pub fn part1(inp: &Matrix<char>) -> usize {
-    let start_pos = find_start(inp);
+#[must_use]
+pub fn part1(inp: &Manifold) -> usize {
+    inp.sources
+        .iter()
+        .flat_map(|&source| reached_splitters(&inp.grid, source))
+        .unique()
+        .count()
+}
+
+fn reached_splitters(grid: &Matrix<char>, source: (usize, usize)) -> HashSet<(usize, usize)> {
+    reached_beams(grid, source)
+        .into_iter()
+        .filter(|beam| grid[beam.pos] == SPLITTER)
+        .map(|beam| beam.pos)
+        .collect()
+}
+
+fn reached_beams(grid: &Matrix<char>, source: (usize, usize)) -> HashSet<Beam> {
+    let start = Beam {
+        pos: source,
+        dir: Direction::Down,
+    };
 
-    let mut num_splits = 0;
+    let mut seen = HashSet::new();
 
     let mut queue = BTreeSet::new();
-    queue.insert(start_pos);
+    queue.insert(start);
+
+    while let Some(beam) = queue.pop_first() {
+        if !seen.insert(beam) {
+            continue;
+        }
 
-    while let Some(p) = queue.pop_first() {
-        num_splits += usize::from(inp[p] == SPLITTER);
-        for succ in successors(inp, p) {
-            queue.insert(succ);
+        for succ in successors(grid, beam) {
+            if let Next::Beam(succ) = succ {
+                queue.insert(succ);
+            }
         }
     }
 
-    num_splits
+    seen
+}
+
+/// Splitters offset a beam to both sides while keeping its direction, mirrors reflect it,
+/// absorbers stop it and merges send it downwards unless it is travelling up, which they block.
+fn successors(matrix: &Matrix<char>, beam: Beam) -> Vec<Next> {
+    let continue_to = |dir: Direction| {
+        let next = Beam { pos: beam.pos, dir };
+        next.moved(matrix, dir)
+            .map_or(Next::Exit(next), |pos| Next::Beam(Beam { pos, dir }))
+    };
+
+    match matrix[beam.pos] {
+        SPLITTER => beam
+            .dir
+            .perpendicular()
+            .into_iter()
+            .filter_map(|side| beam.moved(matrix, side))
+            .map(|pos| Next::Beam(Beam { pos, dir: beam.dir }))
+            .collect_vec(),
+        c @ (MIRROR | BACK_MIRROR) => vec![continue_to(beam.dir.reflect(c))],
+        ABSORBER => vec![],
+        MERGE if beam.dir == Direction::Up => vec![],
+        MERGE => vec![continue_to(Direction::Down)],
+        _ => vec![continue_to(beam.dir)],
+    }
+}
+
+/// Timelines of all sources combined, `None` if beams of any source can loop forever.
+///
+/// A timeline only ends when its beam leaves the manifold, so elements on the bottom row still
+/// act on it: a splitter there splits the beam into two timelines leaving through its neighbours.
+#[aoc(day07, part2)]
+#[must_use]
+pub fn part2(inp: &Manifold) -> Option<BigUint> {
+    inp.sources
+        .iter()
+        .map(|&source| timelines(inp, source).map(|it| it.total))
+        .sum()
+}
+
+pub struct Timelines {
+    /// Number of timelines leaving the manifold on any side
+    pub total: BigUint,
+    /// Number of timelines leaving through each column of the bottom row
+    pub per_column: Vec<BigUint>,
+}
+
+/// `None` if beams can loop forever, e.g. between mirrors.
+#[must_use]
+pub fn timelines(inp: &Manifold, source: (usize, usize)) -> Option<Timelines> {
+    flow(&inp.grid, source).map(|it| it.timelines)
 }
 
-fn successors(matrix: &Matrix<char>, (row, col): (usize, usize)) -> Vec<(usize, usize)> {
-    let mut succs = vec![];
+struct Flow {
+    timelines: Timelines,
+    /// Reachable beam states in topological order
+    order: Vec<Beam>,
+    /// Number of timelines reaching each beam state
+    counts: HashMap<Beam, BigUint>,
+}
+
+/// Processes beam states in topological order, carrying the number of timelines reaching each
+/// state, until they leave the manifold.
+fn flow(grid: &Matrix<char>, source: (usize, usize)) -> Option<Flow> {
+    let start = Beam {
+        pos: source,
+        dir: Direction::Down,
+    };
 
-    if matrix[(row, col)] == SPLITTER {
-        if col > 0 {
-            succs.push((row, col - 1));
+    let mut in_degree = HashMap::from([(start, 0usize)]);
+    let mut stack = vec![start];
+    while let Some(beam) = stack.pop() {
+        for succ in successors(grid, beam) {
+            if let Next::Beam(succ) = succ {
+                let degree = in_degree.entry(succ).or_default();
+                if *degree == 0 && succ != start {
+                    stack.push(succ);
+                }
+
+                *degree += 1;
+            }
         }
+    }
 
-        if col < matrix.columns - 1 {
-            succs.push((row, col + 1));
+    let mut counts = HashMap::from([(start, BigUint::from(1u8))]);
+    let mut total = BigUint::ZERO;
+    let mut per_column = vec![BigUint::ZERO; grid.columns];
+
+    let mut order = vec![];
+    let mut ready = if in_degree[&start] == 0 {
+        vec![start]
+    } else {
+        vec![]
+    };
+
+    while let Some(beam) = ready.pop() {
+        order.push(beam);
+        let count = counts.get(&beam).cloned().unwrap_or_default();
+
+        for succ in successors(grid, beam) {
+            match succ {
+                Next::Beam(succ) => {
+                    *counts.entry(succ).or_default() += &count;
+
+                    let degree = in_degree.get_mut(&succ)?;
+                    *degree -= 1;
+                    if *degree == 0 {
+                        ready.push(succ);
+                    }
+                }
+                Next::Exit(exit) => {
+                    if exit.dir == Direction::Down {
+                        per_column[exit.pos.1] += &count;
+                    }
+
+                    total += &count;
+                }
+            }
         }
-    } else if row < matrix.rows - 1 {
-        succs.push((row + 1, col));
     }
 
-    succs
+    (order.len() == in_degree.len()).then_some(Flow {
+        timelines: Timelines { total, per_column },
+        order,
+        counts,
+    })
 }
 
-#[aoc(day07, part2)]
-pub fn part2(inp: &Matrix<char>) -> usize {
-    let start_pos = find_start(inp);
+/// Number of complete timelines passing through each beam state.
+fn timelines_through(
+    grid: &Matrix<char>,
+    source: (usize, usize),
+) -> Option<HashMap<Beam, BigUint>> {
+    let Flow { order, counts, .. } = flow(grid, source)?;
 
-    pathfinding::prelude::count_paths(
-        start_pos,
-        |&p| successors(inp, p),
-        |&(r, _)| r == inp.rows - 1,
-    )
+    // timelines from each state until leaving the manifold
+    let mut remaining = HashMap::<Beam, BigUint>::new();
+    for &beam in order.iter().rev() {
+        let from = successors(grid, beam)
+            .iter()
+            .map(|succ| match succ {
+                Next::Beam(succ) => remaining[succ].clone(),
+                Next::Exit(_) => BigUint::from(1u8),
+            })
+            .sum();
+        remaining.insert(beam, from);
+    }
+
+    let through = order
+        .iter()
+        .map(|beam| (*beam, &counts[beam] * &remaining[beam]))
+        .collect();
+
+    Some(through)
+}
+
+pub struct SourceReport {
+    pub source: (usize, usize),
+    pub splits: usize,
+    pub timelines: Option<BigUint>,
+}
+
+/// Part 1 and part 2 results for every source on its own.
+#[must_use]
+pub fn per_source(inp: &Manifold) -> Vec<SourceReport> {
+    inp.sources
+        .iter()
+        .map(|&source| SourceReport {
+            source,
+            splits: reached_splitters(&inp.grid, source).len(),
+            timelines: timelines(inp, source).map(|it| it.total),
+        })
+        .collect_vec()
+}
+
+/// Every splitter that is hit by any beam, with the sources whose beams reach it.
+#[must_use]
+pub fn splitter_sources(inp: &Manifold) -> BTreeMap<(usize, usize), Vec<(usize, usize)>> {
+    let mut res = BTreeMap::<_, Vec<_>>::new();
+    for &source in &inp.sources {
+        for splitter in reached_splitters(&inp.grid, source) {
+            res.entry(splitter).or_default().push(source);
+        }
+    }
+
+    res
+}
+
+const DEAD_SPLITTER: char = 'x';
+
+/// Draws the beams of all sources into the manifold: `|` and `-` for cells crossed vertically or
+/// horizontally (`+` for both), splitters that are never hit are replaced by `x`.
+#[must_use]
+pub fn render_text(inp: &Manifold) -> String {
+    let beams = inp
+        .sources
+        .iter()
+        .flat_map(|&source| reached_beams(&inp.grid, source))
+        .collect::<HashSet<_>>();
+
+    let mut grid = inp.grid.clone();
+    for beam in &beams {
+        let vertical = matches!(beam.dir, Direction::Up | Direction::Down);
+        let cell = &mut grid[beam.pos];
+        *cell = match (*cell, vertical) {
+            ('.', true) => '|',
+            ('.', false) => '-',
+            ('-', true) | ('|', false) => '+',
+            (c, _) => c,
+        };
+    }
+
+    let reached = beams.iter().map(|beam| beam.pos).collect::<HashSet<_>>();
+    for (pos, c) in inp.grid.items() {
+        if *c == SPLITTER && !reached.contains(&pos) {
+            grid[pos] = DEAD_SPLITTER;
+        }
+    }
+
+    grid.iter()
+        .map(|row| row.iter().collect::<String>())
+        .join("\n")
 }
 
-fn find_start(inp: &Matrix<char>) -> (usize, usize) {
-    inp.items()
-        .find_map(|(p, c)| if *c == 'S' { Some(p) } else { None })
-        .expect("'S' position in input")
+const CELL_SIZE: usize = 10;
+const MAX_STROKE_WIDTH: f64 = 9.0;
+
+/// SVG of the manifold with the beams drawn in, `None` if beams can loop forever.
+///
+/// The width of the beams is proportional to the number of timelines passing through them.
+/// Hit splitters are black, splitters that are never hit are red.
+#[must_use]
+pub fn render_svg(inp: &Manifold) -> Option<String> {
+    // timelines per cell and axis (vertical or horizontal)
+    let mut segments = BTreeMap::<((usize, usize), bool), BigUint>::new();
+    for &source in &inp.sources {
+        for (beam, count) in timelines_through(&inp.grid, source)? {
+            let vertical = matches!(beam.dir, Direction::Up | Direction::Down);
+            *segments.entry((beam.pos, vertical)).or_default() += count;
+        }
+    }
+
+    let max = segments.values().max().cloned().unwrap_or_default();
+    // scale counts down far enough to be represented exactly by floats
+    let shift = max.bits().saturating_sub(f64::MANTISSA_DIGITS.into());
+    let max = (&max >> shift).to_f64().unwrap_or(1.0).max(1.0);
+
+    let half = CELL_SIZE / 2;
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">"#,
+        inp.grid.columns * CELL_SIZE,
+        inp.grid.rows * CELL_SIZE
+    );
+    svg.push_str("<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n");
+
+    for (((row, col), vertical), count) in &segments {
+        let ratio = (count >> shift).to_f64().unwrap_or_default() / max;
+        let width = 0.5 + ratio * MAX_STROKE_WIDTH;
+        let (x, y) = (col * CELL_SIZE, row * CELL_SIZE);
+        let (x1, y1, x2, y2) = if *vertical {
+            (x + half, y, x + half, y + CELL_SIZE)
+        } else {
+            (x, y + half, x + CELL_SIZE, y + half)
+        };
+
+        let _ = writeln!(
+            svg,
+            r#"<line class="beam" x1="{x1}" y1="{y1}" x2="{x2}" y2="{y2}" stroke="orange" stroke-width="{width:.2}"/>"#
+        );
+    }
+
+    for ((row, col), c) in inp.grid.items() {
+        let (x, y) = (col * CELL_SIZE, row * CELL_SIZE);
+        match *c {
+            SPLITTER => {
+                let hit = segments.contains_key(&((row, col), true))
+                    || segments.contains_key(&((row, col), false));
+                let (class, fill) = if hit {
+                    ("hit", "black")
+                } else {
+                    ("dead", "red")
+                };
+                let _ = writeln!(
+                    svg,
+                    r#"<polygon class="splitter {class}" points="{},{} {},{} {},{}" fill="{fill}"/>"#,
+                    x + half,
+                    y,
+                    x,
+                    y + CELL_SIZE,
+                    x + CELL_SIZE,
+                    y + CELL_SIZE
+                );
+            }
+            MIRROR | BACK_MIRROR => {
+                let (y1, y2) = if *c == MIRROR {
+                    (y + CELL_SIZE, y)
+                } else {
+                    (y, y + CELL_SIZE)
+                };
+                let _ = writeln!(
+                    svg,
+                    r#"<line class="mirror" x1="{x}" y1="{y1}" x2="{}" y2="{y2}" stroke="blue"/>"#,
+                    x + CELL_SIZE
+                );
+            }
+            ABSORBER => {
+                let _ = writeln!(
+                    svg,
+                    r#"<rect class="absorber" x="{x}" y="{y}" width="{CELL_SIZE}" height="{CELL_SIZE}" fill="gray"/>"#
+                );
+            }
+            _ => {}
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    Some(svg)
 }
 
 #[cfg(test)]
@@ -96,6 +513,111 @@ mod tests {
     fn test_p2() {
         let data = generate(TEST_INPUT).expect("valid input");
         let res = part2(&data);
-        assert_eq!(res, 40);
+        assert_eq!(res, Some(BigUint::from(40u8)));
+    }
+
+    #[test]
+    fn test_timelines() {
+        let data = generate(TEST_INPUT).expect("valid input");
+        let res = timelines(&data, data.sources()[0]).expect("no loops");
+        assert_eq!(
+            res.per_column,
+            [1u8, 0, 2, 0, 10, 0, 11, 0, 11, 0, 2, 1, 1, 0, 1].map(BigUint::from)
+        );
+
+        // every row of splitters doubles the number of timelines
+        let rows = 200;
+        let mut input = vec![".S.".to_owned()];
+        for _ in 0..rows {
+            input.push(".^.".to_owned());
+            input.push("^.^".to_owned());
+        }
+
+        let data = generate(&input.join("\n")).expect("valid input");
+        assert_eq!(part2(&data), Some(BigUint::from(2u8).pow(rows)));
+
+        // a splitter on the bottom row splits before the beam leaves
+        let data = generate(".S.\n.^.").expect("valid input");
+        assert_eq!(part1(&data), 1);
+        assert_eq!(part2(&data), Some(BigUint::from(2u8)));
+        let res = timelines(&data, data.sources()[0]).expect("no loops");
+        assert_eq!(res.per_column, [1u8, 0, 1].map(BigUint::from));
+
+        // while a side of the split that would leave sideways is lost
+        let data = generate("S.\n^.").expect("valid input");
+        assert_eq!(part2(&data), Some(BigUint::from(1u8)));
+    }
+
+    #[test]
+    fn test_elements() {
+        // mirrors turn the beam right and into a merge that sends it down again
+        let data = generate(".S...\n.\\.v.\n.....").expect("valid input");
+        let res = timelines(&data, data.sources()[0]).expect("no loops");
+        assert_eq!(res.per_column, [0u8, 0, 0, 1, 0].map(BigUint::from));
+
+        // one side of the split is absorbed, the other one leaves to the right
+        let data = generate("..S..\n..^..\n.#.\\.\n.....").expect("valid input");
+        assert_eq!(part1(&data), 1);
+        let res = timelines(&data, data.sources()[0]).expect("no loops");
+        assert_eq!(res.total, BigUint::from(1u8));
+        assert!(res.per_column.iter().all(|it| *it == BigUint::ZERO));
+
+        // going up through a merge is blocked
+        let data = generate("Sv\n\\/").expect("valid input");
+        assert_eq!(part2(&data), Some(BigUint::ZERO));
+
+        // adjacent splitters bounce beams back and forth forever
+        let data = generate("..S.\n.^^.").expect("valid input");
+        assert_eq!(part2(&data), None);
+    }
+
+    #[test]
+    fn test_sources() {
+        // a second source straight above the splitter in the bottom left
+        let input = TEST_INPUT.replacen(".......S", ".S.....S", 1);
+        let data = generate(&input).expect("valid input");
+        assert_eq!(data.sources(), [(0, 1), (0, 7)]);
+
+        let reports = per_source(&data);
+        let results = reports
+            .iter()
+            .map(|it| (it.source, it.splits, it.timelines.clone()))
+            .collect_vec();
+        assert_eq!(
+            results,
+            [
+                ((0, 1), 1, Some(BigUint::from(2u8))),
+                ((0, 7), 21, Some(BigUint::from(40u8)))
+            ]
+        );
+
+        assert_eq!(part1(&data), 21);
+        assert_eq!(part2(&data), Some(BigUint::from(42u8)));
+
+        let reached = splitter_sources(&data);
+        assert_eq!(reached.len(), 21);
+        assert_eq!(reached[&(14, 1)], [(0, 1), (0, 7)]);
+        assert_eq!(reached[&(2, 7)], [(0, 7)]);
+
+        let data = generate(&format!("sources: 0,1\n{TEST_INPUT}")).expect("valid input");
+        assert_eq!(data.sources(), [(0, 1), (0, 7)]);
+
+        assert!(generate("...\n.^.").is_none());
+    }
+
+    #[test]
+    fn test_render() {
+        let data = generate("..S..\n..^..\n.....\n^...^\n.\\...").expect("valid input");
+        assert_eq!(render_text(&data), "..S..\n.|^|.\n.|.|.\nx|.|x\n.\\-+-");
+
+        let svg = render_svg(&data).expect("no loops");
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("splitter hit").count(), 1);
+        assert_eq!(svg.matches("splitter dead").count(), 2);
+        assert_eq!(svg.matches(r#"class="beam""#).count(), 13);
+
+        // both timelines pass the source and splitter, each of the branches only carries one
+        assert_eq!(svg.matches(r#"stroke-width="9.50""#).count(), 2);
+        assert_eq!(svg.matches(r#"stroke-width="5.00""#).count(), 11);
     }
 }
@@ -1,7 +1,10 @@
+use crate::union_find::UnionFind;
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
 use parse_display_derive::{Display, FromStr};
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt::Write;
 
 #[derive(Display, FromStr, Copy, Clone, Eq, PartialEq, Hash, Debug)]
 #[display("{x},{y},{z}")]
@@ -12,96 +15,458 @@ pub struct JunctionBox {
 }
 
 impl JunctionBox {
-    pub const fn distance_to(&self, target: &Self) -> isize {
-        (self.x - target.x).pow(2) + (self.y - target.y).pow(2) + (self.z - target.z).pow(2)
+    #[must_use]
+    pub fn distance_to(&self, target: &Self, metric: Metric) -> isize {
+        let deltas = (0..3).map(|axis| (self.coord(axis) - target.coord(axis), axis));
+        match metric {
+            Metric::Euclidean => deltas.map(|(d, _)| d.pow(2)).sum(),
+            Metric::Chebyshev => deltas.map(|(d, _)| d.abs()).max().unwrap_or_default(),
+            Metric::Manhattan | Metric::Weighted(..) => {
+                deltas.map(|(d, axis)| metric.axis_distance(axis, d)).sum()
+            }
+        }
+    }
+
+    const fn coord(&self, axis: usize) -> isize {
+        match axis {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        }
+    }
+}
+
+/// Distance used to order the pairs of junction boxes.
+#[derive(Display, FromStr, Copy, Clone, Default, Eq, PartialEq, Hash, Debug)]
+#[display(style = "lowercase")]
+pub enum Metric {
+    /// Squared straight-line distance, so that distances stay exact integers
+    #[default]
+    Euclidean,
+    /// Sum of the distances along each axis
+    Manhattan,
+    /// Largest distance along any axis
+    Chebyshev,
+    /// Manhattan distance with a cost per unit along each axis
+    #[display("weighted {0}")]
+    Weighted(Weights),
+}
+
+/// Non-negative costs per unit along x, y and z, negative costs would let distances shrink with
+/// every step along an axis.
+#[derive(Display, FromStr, Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[display("{x},{y},{z}")]
+#[from_str(new = Self::new(x, y, z))]
+pub struct Weights {
+    x: isize,
+    y: isize,
+    z: isize,
+}
+
+impl Weights {
+    /// `None` if any weight is negative.
+    #[must_use]
+    pub const fn new(x: isize, y: isize, z: isize) -> Option<Self> {
+        if x < 0 || y < 0 || z < 0 {
+            return None;
+        }
+
+        Some(Self { x, y, z })
+    }
+}
+
+impl Metric {
+    /// Lower bound of the distance between two boxes that are `delta` apart along `axis`.
+    const fn axis_distance(self, axis: usize, delta: isize) -> isize {
+        match self {
+            Self::Euclidean => delta.pow(2),
+            Self::Manhattan | Self::Chebyshev => delta.abs(),
+            Self::Weighted(weights) => {
+                let weight = match axis {
+                    0 => weights.x,
+                    1 => weights.y,
+                    _ => weights.z,
+                };
+                weight * delta.abs()
+            }
+        }
+    }
+
+    /// Cable length for a distance of this metric.
+    #[allow(clippy::cast_precision_loss)]
+    fn length(self, distance: isize) -> f64 {
+        match self {
+            Self::Euclidean => (distance as f64).sqrt(),
+            _ => distance as f64,
+        }
     }
 }
 
 #[aoc_generator(day08)]
+#[must_use]
 pub fn generate(s: &str) -> Vec<JunctionBox> {
     s.lines()
         .filter_map(|l| l.parse::<JunctionBox>().ok())
         .collect_vec()
 }
 
+pub const DEFAULT_CONNECTIONS: usize = 1000;
+pub const DEFAULT_TOP_K: usize = 3;
+
 #[aoc(day08, part1)]
+#[must_use]
 pub fn part1(inp: &[JunctionBox]) -> usize {
-    let junction_pairs = generate_pairs(inp);
-    solve_p1(inp, &junction_pairs[..1000])
+    largest_circuits(inp, Metric::default(), DEFAULT_CONNECTIONS, DEFAULT_TOP_K)
 }
 
 #[aoc(day08, part2)]
+#[must_use]
 pub fn part2(inp: &[JunctionBox]) -> Option<isize> {
-    let junction_pairs = generate_pairs(inp);
-
-    let mut circuits = HashMap::new();
-    let mut circuit_id = 1;
-    for (first, other) in &junction_pairs {
-        if circuits.contains_key(first) && circuits.contains_key(other) {
-            // both circuits already connected to something
-            // update all of "other" to same circuit as "first"
-            let first_id = *circuits.get(first)?;
-            let other_id = *circuits.get(other)?;
-
-            for circ in circuits.values_mut() {
-                if *circ == other_id {
-                    *circ = first_id;
-                }
+    last_connection(inp, Metric::default())
+}
+
+/// Product of the x coordinates of the last two boxes joined into a single circuit.
+#[must_use]
+pub fn last_connection(inp: &[JunctionBox], metric: Metric) -> Option<isize> {
+    let last = spanning_tree(inp, metric).cables.pop()?;
+    Some(inp[last.from].x * inp[last.to].x)
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Cable {
+    pub from: usize,
+    pub to: usize,
+    pub length: f64,
+}
+
+/// Minimum spanning tree of the junction boxes.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SpanningTree {
+    /// Cables in the order they were added, i.e. by increasing length
+    pub cables: Vec<Cable>,
+    pub total_length: f64,
+}
+
+/// Connects the closest pairs that are not yet in the same circuit until all boxes are connected
+/// (Kruskal's algorithm).
+#[must_use]
+pub fn spanning_tree(inp: &[JunctionBox], metric: Metric) -> SpanningTree {
+    let mut circuits = UnionFind::new(inp.len());
+    let mut cables = vec![];
+    for (from, to) in generate_pairs(inp, metric) {
+        if circuits.num_components() <= 1 {
+            break;
+        }
+
+        if circuits.union(from, to) {
+            let length = metric.length(inp[from].distance_to(&inp[to], metric));
+            cables.push(Cable { from, to, length });
+        }
+    }
+
+    SpanningTree {
+        total_length: cables.iter().map(|c| c.length).sum(),
+        cables,
+    }
+}
+
+impl SpanningTree {
+    /// Graphviz graph with one node per junction box, labelled with its position.
+    #[must_use]
+    pub fn to_dot(&self, inp: &[JunctionBox]) -> String {
+        let mut dot = String::from("graph circuits {\n");
+        for (idx, junction_box) in inp.iter().enumerate() {
+            let _ = writeln!(dot, "    {idx} [label=\"{junction_box}\"];");
+        }
+        for (step, cable) in self.cables.iter().enumerate() {
+            let _ = writeln!(
+                dot,
+                "    {} -- {} [label=\"{:.3}\", step={}];",
+                cable.from,
+                cable.to,
+                cable.length,
+                step + 1
+            );
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    /// One line per cable in insertion order, with the positions of both ends.
+    #[must_use]
+    pub fn to_csv(&self, inp: &[JunctionBox]) -> String {
+        let mut csv = String::from("step,from,to,from_x,from_y,from_z,to_x,to_y,to_z,length\n");
+        for (step, cable) in self.cables.iter().enumerate() {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{:.3}",
+                step + 1,
+                cable.from,
+                cable.to,
+                inp[cable.from],
+                inp[cable.to],
+                cable.length
+            );
+        }
+
+        csv
+    }
+}
+
+/// Index pairs of all junction boxes, closest first. Pairs at the same distance are ordered by
+/// their indices.
+#[must_use]
+pub fn generate_pairs(inp: &[JunctionBox], metric: Metric) -> Pairs<'_> {
+    Pairs::new(inp, metric)
+}
+
+/// k-d tree over the junction boxes, stored implicitly: the median of each slice of `nodes` is a
+/// node splitting its subtrees along the axis given by its depth.
+struct KdTree {
+    nodes: Vec<usize>,
+}
+
+impl KdTree {
+    fn new(boxes: &[JunctionBox]) -> Self {
+        let mut nodes = (0..boxes.len()).collect_vec();
+        Self::build(&mut nodes, boxes, 0);
+        Self { nodes }
+    }
+
+    fn build(nodes: &mut [usize], boxes: &[JunctionBox], depth: usize) {
+        if nodes.len() <= 1 {
+            return;
+        }
+
+        let mid = nodes.len() / 2;
+        nodes.select_nth_unstable_by_key(mid, |&idx| boxes[idx].coord(depth % 3));
+
+        let (left, right) = nodes.split_at_mut(mid);
+        Self::build(left, boxes, depth + 1);
+        Self::build(&mut right[1..], boxes, depth + 1);
+    }
+
+    /// The `k` boxes closest to `target` (excluding itself) as `(distance, index)`, closest first.
+    fn nearest(
+        &self,
+        boxes: &[JunctionBox],
+        metric: Metric,
+        target: usize,
+        k: usize,
+    ) -> Vec<(isize, usize)> {
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        Self::search(&self.nodes, boxes, metric, 0, target, k, &mut heap);
+        heap.into_sorted_vec()
+    }
+
+    fn search(
+        nodes: &[usize],
+        boxes: &[JunctionBox],
+        metric: Metric,
+        depth: usize,
+        target: usize,
+        k: usize,
+        heap: &mut BinaryHeap<(isize, usize)>,
+    ) {
+        if nodes.is_empty() {
+            return;
+        }
+
+        let mid = nodes.len() / 2;
+        let node = nodes[mid];
+
+        if node != target {
+            heap.push((boxes[target].distance_to(&boxes[node], metric), node));
+            if heap.len() > k {
+                heap.pop();
             }
+        }
+
+        let axis = depth % 3;
+        let delta = boxes[target].coord(axis) - boxes[node].coord(axis);
+        let (near, far) = if delta < 0 {
+            (&nodes[..mid], &nodes[mid + 1..])
         } else {
-            // at least one of the boxes is not connected yet
-            assert!(!circuits.contains_key(first) || !circuits.contains_key(other));
+            (&nodes[mid + 1..], &nodes[..mid])
+        };
+
+        Self::search(near, boxes, metric, depth + 1, target, k, heap);
 
-            let num = *circuits
-                .get(first)
-                .or_else(|| circuits.get(other))
-                .unwrap_or_else(|| {
-                    circuit_id += 1;
-                    &circuit_id
-                });
+        // boxes on the far side are at least as far away as the splitting plane, ties are kept
+        // so that equally distant boxes are always chosen by index
+        let bound = metric.axis_distance(axis, delta);
+        if heap.len() < k || heap.peek().is_some_and(|&(dist, _)| bound <= dist) {
+            Self::search(far, boxes, metric, depth + 1, target, k, heap);
+        }
+    }
+}
+
+/// Lazily merges the nearest-neighbour lists of all boxes, so that only as many pairs are computed
+/// as are consumed. Each pair is produced by the neighbour list of its lower index.
+pub struct Pairs<'a> {
+    boxes: &'a [JunctionBox],
+    metric: Metric,
+    tree: KdTree,
+    /// Per box: number of nearest neighbours queried so far and those with a higher index
+    neighbours: Vec<(usize, Vec<(isize, usize)>)>,
+    /// Next pair of each neighbour list as (distance, box, neighbour, position in list)
+    heap: BinaryHeap<Reverse<(isize, usize, usize, usize)>>,
+}
 
-            *circuits.entry(*first).or_default() = num;
-            *circuits.entry(*other).or_default() = num;
+const INITIAL_NEIGHBOURS: usize = 8;
+
+impl<'a> Pairs<'a> {
+    fn new(boxes: &'a [JunctionBox], metric: Metric) -> Self {
+        let mut pairs = Self {
+            boxes,
+            metric,
+            tree: KdTree::new(boxes),
+            neighbours: vec![(0, vec![]); boxes.len()],
+            heap: BinaryHeap::new(),
+        };
+
+        for idx in 0..boxes.len() {
+            pairs.push_neighbour(idx, 0);
         }
 
-        if circuits.values().all_equal() && circuits.len() == inp.len() {
-            // all nodes inserted and all part of the same circuit
-            return Some(first.x * other.x);
+        pairs
+    }
+
+    /// Queues the neighbour at `pos` in the list of `idx`, querying more neighbours if needed.
+    fn push_neighbour(&mut self, idx: usize, pos: usize) {
+        let max_neighbours = self.boxes.len() - 1;
+
+        let (queried, candidates) = &mut self.neighbours[idx];
+        while pos >= candidates.len() && *queried < max_neighbours {
+            *queried = (*queried * 2).max(INITIAL_NEIGHBOURS).min(max_neighbours);
+            // the k nearest are ordered by (distance, index), so the previous list is a prefix
+            *candidates = self
+                .tree
+                .nearest(self.boxes, self.metric, idx, *queried)
+                .into_iter()
+                .filter(|&(_, other)| other > idx)
+                .collect_vec();
+        }
+
+        if let Some(&(dist, other)) = candidates.get(pos) {
+            self.heap.push(Reverse((dist, idx, other, pos)));
         }
     }
+}
+
+impl Iterator for Pairs<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((_, idx, other, pos)) = self.heap.pop()?;
+        self.push_neighbour(idx, pos + 1);
 
-    unreachable!("no solution found")
+        Some((idx, other))
+    }
 }
 
-fn generate_pairs(inp: &[JunctionBox]) -> Vec<(JunctionBox, JunctionBox)> {
-    inp.iter()
-        .combinations(2)
-        .sorted_by_key(|a| a[0].distance_to(a[1]))
-        .map(|a| (*a[0], *a[1]))
-        .collect_vec()
+/// Connects the `connections` closest pairs (or all pairs if there are fewer) and multiplies the
+/// sizes of the `top_k` largest circuits.
+#[must_use]
+pub fn largest_circuits(
+    inp: &[JunctionBox],
+    metric: Metric,
+    connections: usize,
+    top_k: usize,
+) -> usize {
+    let mut circuits = UnionFind::new(inp.len());
+    for (l, r) in generate_pairs(inp, metric).take(connections) {
+        circuits.union(l, r);
+    }
+
+    circuits.component_sizes().k_largest(top_k).product()
 }
 
-fn solve_p1(inp: &[JunctionBox], junction_pairs: &[(JunctionBox, JunctionBox)]) -> usize {
-    let connected_components = pathfinding::prelude::connected_components(inp, |junction_box| {
-        junction_pairs
-            .iter()
-            .filter_map(|(l, r)| {
-                if *l == *junction_box {
-                    Some(*r)
-                } else if *r == *junction_box {
-                    Some(*l)
-                } else {
-                    None
-                }
-            })
+/// History of all circuit merges along the pair sequence, built once for answering queries about
+/// any point in time.
+///
+/// Merges form a tree (Kruskal reconstruction tree): the boxes are its leaves and each merge is a
+/// node joining the two circuits it connected, so a node is an existing circuit for all steps
+/// between its own merge and that of its parent.
+pub struct Timeline {
+    /// Merge node each node was joined into
+    parent: Vec<Option<usize>>,
+    /// Number of pairs connected when the node was created, `0` for boxes
+    step: Vec<usize>,
+    size: Vec<usize>,
+}
+
+#[must_use]
+pub fn timeline(inp: &[JunctionBox], metric: Metric) -> Timeline {
+    let mut timeline = Timeline {
+        parent: vec![None; inp.len()],
+        step: vec![0; inp.len()],
+        size: vec![1; inp.len()],
+    };
+
+    let mut circuits = UnionFind::new(inp.len());
+    // merge node of each union-find root
+    let mut nodes = (0..inp.len()).collect_vec();
+    for (idx, (l, r)) in generate_pairs(inp, metric).enumerate() {
+        if circuits.num_components() <= 1 {
+            break;
+        }
+
+        let (left, right) = (nodes[circuits.find(l)], nodes[circuits.find(r)]);
+        if circuits.union(l, r) {
+            let node = timeline.parent.len();
+            timeline.parent[left] = Some(node);
+            timeline.parent[right] = Some(node);
+            timeline.parent.push(None);
+            timeline.step.push(idx + 1);
+            timeline
+                .size
+                .push(timeline.size[left] + timeline.size[right]);
+            nodes[circuits.find(l)] = node;
+        }
+    }
+
+    timeline
+}
+
+impl Timeline {
+    /// Nodes that are circuits after connecting the first `pairs` pairs.
+    fn circuits(&self, pairs: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..self.parent.len()).filter(move |&node| {
+            self.step[node] <= pairs && self.parent[node].is_none_or(|p| self.step[p] > pairs)
+        })
+    }
+
+    #[must_use]
+    pub fn num_circuits(&self, pairs: usize) -> usize {
+        self.circuits(pairs).count()
+    }
+
+    /// Sizes of the circuits after connecting the first `pairs` pairs, largest first.
+    #[must_use]
+    pub fn circuit_sizes(&self, pairs: usize) -> Vec<usize> {
+        self.circuits(pairs)
+            .map(|node| self.size[node])
+            .sorted_unstable_by(|l, r| r.cmp(l))
             .collect_vec()
-    });
+    }
 
-    connected_components
-        .iter()
-        .map(HashSet::len)
-        .k_largest(3)
-        .product()
+    /// Number of pairs that have to be connected for `a` and `b` to be in the same circuit.
+    #[must_use]
+    pub fn connected_at(&self, a: usize, b: usize) -> Option<usize> {
+        // the first common ancestor is the merge that joined both, later merges have later steps
+        let (mut a, mut b) = (a, b);
+        while a != b {
+            if self.step[a] <= self.step[b] {
+                a = self.parent[a]?;
+            } else {
+                b = self.parent[b]?;
+            }
+        }
+
+        Some(self.step[a])
+    }
 }
 
 #[cfg(test)]
@@ -132,9 +497,12 @@ mod tests {
     #[test]
     fn test_p1() {
         let data = generate(TEST_INPUT);
-        let junction_pairs = generate_pairs(&data);
-        let res = solve_p1(&data, &junction_pairs[..10]);
+        let res = largest_circuits(&data, Metric::Euclidean, 10, DEFAULT_TOP_K);
         assert_eq!(res, 40);
+
+        // more connections than pairs joins everything into one circuit
+        assert_eq!(part1(&data), 20);
+        assert_eq!(largest_circuits(&data, Metric::Euclidean, 10, 1), 5);
     }
 
     #[test]
@@ -143,4 +511,122 @@ mod tests {
         let res = part2(&data);
         assert_eq!(res, Some(25_272));
     }
+
+    #[test]
+    fn test_pairs_by_distance() {
+        // pseudo-random boxes with many equal distances
+        let mut seed = 42usize;
+        let mut rand = || {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            (seed >> 33).cast_signed() % 50
+        };
+        let boxes = (0..300)
+            .map(|_| JunctionBox {
+                x: rand(),
+                y: rand(),
+                z: rand(),
+            })
+            .collect_vec();
+
+        let metrics = [
+            Metric::Euclidean,
+            Metric::Manhattan,
+            Metric::Chebyshev,
+            Metric::Weighted(Weights::new(1, 1, 2).expect("valid weights")),
+        ];
+        for (boxes, metric) in [generate(TEST_INPUT), boxes]
+            .iter()
+            .cartesian_product(metrics)
+        {
+            let expected = (0..boxes.len())
+                .tuple_combinations()
+                .sorted_by_key(|&(l, r)| boxes[l].distance_to(&boxes[r], metric))
+                .collect_vec();
+            assert_eq!(generate_pairs(boxes, metric).collect_vec(), expected);
+        }
+    }
+
+    #[test]
+    fn test_spanning_tree() {
+        let data = generate(TEST_INPUT);
+        let tree = spanning_tree(&data, Metric::Euclidean);
+        assert_eq!(tree.cables.len(), data.len() - 1);
+        assert!(tree.cables.is_sorted_by(|l, r| l.length <= r.length));
+        assert!(
+            (tree.total_length - tree.cables.iter().map(|c| c.length).sum::<f64>()).abs() < 1e-9
+        );
+
+        let first = tree.cables[0];
+        assert_eq!((first.from, first.to), (0, 19));
+
+        let csv = tree.to_csv(&data);
+        assert_eq!(csv.lines().count(), data.len());
+        assert!(
+            csv.lines()
+                .nth(1)
+                .is_some_and(|l| l.starts_with("1,0,19,162,817,812,425,690,689,"))
+        );
+
+        let dot = tree.to_dot(&data);
+        assert!(dot.starts_with("graph circuits {\n    0 [label=\"162,817,812\"];\n"));
+        assert!(dot.contains("    0 -- 19 [label="));
+    }
+
+    #[test]
+    fn test_metrics() {
+        let (a, b) = (
+            JunctionBox { x: 0, y: 0, z: 0 },
+            JunctionBox { x: 1, y: -2, z: 3 },
+        );
+        assert_eq!(a.distance_to(&b, Metric::Euclidean), 14);
+        assert_eq!(a.distance_to(&b, Metric::Manhattan), 6);
+        assert_eq!(a.distance_to(&b, Metric::Chebyshev), 3);
+        let weights = Weights::new(1, 1, 2).expect("valid weights");
+        assert_eq!(a.distance_to(&b, Metric::Weighted(weights)), 9);
+
+        assert_eq!("weighted 1,1,2".parse(), Ok(Metric::Weighted(weights)));
+        assert_eq!(Metric::Weighted(weights).to_string(), "weighted 1,1,2");
+        assert!("weighted 1,1,-3".parse::<Metric>().is_err());
+        assert_eq!(Weights::new(1, -1, 0), None);
+        assert_eq!(Metric::Manhattan.to_string(), "manhattan");
+
+        let data = generate(TEST_INPUT);
+        let tree = spanning_tree(&data, Metric::Manhattan);
+        assert_eq!(tree.cables.len(), data.len() - 1);
+        assert!(tree.cables.iter().all(|c| c.length.fract() == 0.0));
+        assert_eq!(last_connection(&data, Metric::Euclidean), part2(&data));
+    }
+
+    #[test]
+    fn test_timeline() {
+        let data = generate(TEST_INPUT);
+        let res = timeline(&data, Metric::Euclidean);
+
+        assert_eq!(res.num_circuits(0), data.len());
+        assert_eq!(res.num_circuits(10), 11);
+        assert_eq!(res.circuit_sizes(10)[..3], [5, 4, 2]);
+
+        let pairs = generate_pairs(&data, Metric::Euclidean).collect_vec();
+        for steps in [0, 1, 10, 30, pairs.len()] {
+            let mut circuits = UnionFind::new(data.len());
+            for &(l, r) in &pairs[..steps] {
+                circuits.union(l, r);
+            }
+
+            let expected = circuits.component_sizes().sorted().rev().collect_vec();
+            assert_eq!(res.circuit_sizes(steps), expected);
+
+            for (a, b) in (0..data.len()).tuple_combinations() {
+                let connected = res.connected_at(a, b).is_some_and(|step| step <= steps);
+                assert_eq!(connected, circuits.connected(a, b));
+            }
+        }
+
+        assert_eq!(res.connected_at(pairs[0].0, pairs[0].1), Some(1));
+        assert_eq!(res.connected_at(3, 3), Some(0));
+        assert_eq!(
+            timeline(&data[..2], Metric::Euclidean).connected_at(0, 1),
+            Some(1)
+        );
+    }
 }
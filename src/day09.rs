@@ -1,63 +1,479 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use geo::Contains;
-use geo_types::{Coord, LineString, Polygon, Rect};
 use itertools::Itertools;
-use rayon::iter::{ParallelBridge, ParallelIterator};
+use parse_display_derive::{Display, FromStr};
+use pathfinding::matrix::Matrix;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::ops::Range;
 
+#[derive(Display, FromStr, Copy, Clone, Default, Eq, PartialEq, Debug)]
+#[display(style = "lowercase")]
+pub enum Kind {
+    /// Usable floor
+    #[default]
+    Region,
+    /// Obstacle within a region such as a pillar, all tiles on and inside its outline are blocked
+    Hole,
+}
+
+/// Vertices of all outlines in input order, indices refer to positions in this list.
+pub struct FloorPlan {
+    vertices: Vec<(isize, isize)>,
+    outlines: Vec<(Kind, Range<usize>)>,
+}
+
+impl FloorPlan {
+    #[must_use]
+    pub fn new(outlines: impl IntoIterator<Item = (Kind, Vec<(isize, isize)>)>) -> Self {
+        let mut plan = Self {
+            vertices: vec![],
+            outlines: vec![],
+        };
+
+        for (kind, vertices) in outlines {
+            let start = plan.vertices.len();
+            plan.vertices.extend(vertices);
+            plan.outlines.push((kind, start..plan.vertices.len()));
+        }
+
+        plan
+    }
+
+    #[must_use]
+    pub fn vertices(&self) -> &[(isize, isize)] {
+        &self.vertices
+    }
+
+    pub fn outlines(&self) -> impl Iterator<Item = (Kind, &[(isize, isize)])> {
+        self.outlines
+            .iter()
+            .map(|(kind, range)| (*kind, &self.vertices[range.clone()]))
+    }
+}
+
+/// One outline per block, blocks are separated by blank lines. A block may start with a
+/// `kind: hole` or `kind: region` header, without one it is a region. Additional blank lines are
+/// ignored.
 #[aoc_generator(day09)]
-pub fn generate(s: &str) -> Option<Vec<(isize, isize)>> {
-    let mut res = vec![];
+#[must_use]
+pub fn generate(s: &str) -> Option<FloorPlan> {
+    let mut outlines = vec![];
+
+    for block in s.split("\n\n") {
+        let mut lines = block.lines().filter(|l| !l.trim().is_empty()).peekable();
+        if lines.peek().is_none() {
+            continue;
+        }
 
-    for line in s.lines() {
-        let mut spl = line.split(',');
-        let lhs = spl.next().and_then(|it| it.parse::<isize>().ok())?;
-        let rhs = spl.next().and_then(|it| it.parse::<isize>().ok())?;
+        let kind = match lines.peek().and_then(|l| l.strip_prefix("kind: ")) {
+            Some(kind) => {
+                lines.next();
+                kind.parse::<Kind>().ok()?
+            }
+            None => Kind::default(),
+        };
 
-        res.push((lhs, rhs));
+        let mut res = vec![];
+        for line in lines {
+            let mut spl = line.split(',');
+            let lhs = spl.next().and_then(|it| it.parse::<isize>().ok())?;
+            let rhs = spl.next().and_then(|it| it.parse::<isize>().ok())?;
+
+            res.push((lhs, rhs));
+        }
+
+        outlines.push((kind, res));
     }
 
-    Some(res)
+    Some(FloorPlan::new(outlines))
 }
 
-const fn area((lx, ly): (isize, isize), (rx, ry): (isize, isize)) -> usize {
-    (lx.abs_diff(rx) + 1) * (ly.abs_diff(ry) + 1)
+const fn area((lx, ly): (isize, isize), (rx, ry): (isize, isize)) -> u128 {
+    (lx.abs_diff(rx) as u128 + 1) * (ly.abs_diff(ry) as u128 + 1)
 }
 
 #[aoc(day09, part1)]
-pub fn part1(inp: &[(isize, isize)]) -> Option<usize> {
-    inp.iter()
-        .combinations(2)
-        .map(|it| area(*it[0], *it[1]))
-        .max()
+#[must_use]
+pub fn part1(inp: &FloorPlan) -> Option<u128> {
+    largest_rectangles(inp).map(|res| res.area)
 }
 
 #[aoc(day09, part2)]
-#[allow(clippy::cast_precision_loss)]
-pub fn part2(inp: &[(isize, isize)]) -> Option<usize> {
-    let coords = inp
-        .iter()
-        .map(|&(l, r)| Coord {
-            x: l as f64,
-            y: r as f64,
-        })
-        .collect_vec();
+#[must_use]
+pub fn part2(inp: &FloorPlan) -> Option<u128> {
+    largest_enclosed_rectangles(inp).map(|res| res.area)
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Rectangle {
+    /// The two vertices at opposite corners, in input order
+    pub corners: [(isize, isize); 2],
+    /// Smallest x and y of the rectangle
+    pub min: (isize, isize),
+    /// Largest x and y of the rectangle
+    pub max: (isize, isize),
+}
+
+impl Rectangle {
+    fn new(l: (isize, isize), r: (isize, isize)) -> Self {
+        Self {
+            corners: [l, r],
+            min: (l.0.min(r.0), l.1.min(r.1)),
+            max: (l.0.max(r.0), l.1.max(r.1)),
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Placement {
+    pub area: u128,
+    /// All rectangles with the largest area, ordered by the input position of their corners
+    pub rectangles: Vec<Rectangle>,
+}
+
+/// Largest rectangles with corners at any two vertices.
+#[must_use]
+pub fn largest_rectangles(inp: &FloorPlan) -> Option<Placement> {
+    largest(&inp.vertices, |_, _| true)
+}
 
-    let poly = Polygon::new(LineString(coords.clone()), vec![]);
+/// Largest rectangles with corners at two vertices that lie entirely within the regions and
+/// outside of all holes, `None` if any outline is not a valid polygon.
+#[must_use]
+pub fn largest_enclosed_rectangles(inp: &FloorPlan) -> Option<Placement> {
+    validate(inp).ok()?;
+    let floor = Floor::new(inp);
+    largest(&inp.vertices, |l, r| floor.contains(l, r))
+}
+
+fn largest(
+    inp: &[(isize, isize)],
+    valid: impl Fn((isize, isize), (isize, isize)) -> bool + Sync,
+) -> Option<Placement> {
+    let (area, mut pairs) = (0..inp.len())
+        .into_par_iter()
+        .flat_map_iter(|l| (l + 1..inp.len()).map(move |r| (l, r)))
+        .filter(|&(l, r)| valid(inp[l], inp[r]))
+        .fold(
+            || (0, vec![]),
+            |(best, mut pairs), (l, r)| {
+                let area = area(inp[l], inp[r]);
+                if area > best {
+                    (area, vec![(l, r)])
+                } else {
+                    if area == best {
+                        pairs.push((l, r));
+                    }
+                    (best, pairs)
+                }
+            },
+        )
+        .reduce(
+            || (0, vec![]),
+            |(l_best, mut l_pairs), (r_best, r_pairs)| match l_best.cmp(&r_best) {
+                Ordering::Less => (r_best, r_pairs),
+                Ordering::Greater => (l_best, l_pairs),
+                Ordering::Equal => {
+                    l_pairs.extend(r_pairs);
+                    (l_best, l_pairs)
+                }
+            },
+        );
+
+    if pairs.is_empty() {
+        return None;
+    }
+
+    pairs.sort_unstable();
+    Some(Placement {
+        area,
+        rectangles: pairs
+            .into_iter()
+            .map(|(l, r)| Rectangle::new(inp[l], inp[r]))
+            .collect_vec(),
+    })
+}
+
+/// Why the vertices do not form a simple, closed, axis-aligned polygon, edges are given as the
+/// indices of their vertices.
+#[derive(Display, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Defect {
+    #[display("vertices {0} and {1} are the same tile")]
+    Duplicate(usize, usize),
+    #[display("edge {0}-{1} is not axis-aligned")]
+    Diagonal(usize, usize),
+    #[display("edges {0}-{1} and {2}-{3} intersect")]
+    Intersection(usize, usize, usize, usize),
+    /// Two regions or two holes share some of their area, given as indices of the outlines
+    #[display("outlines {0} and {1} overlap")]
+    Overlap(usize, usize),
+    /// A hole reaches outside of all regions, given as the index of its outline
+    #[display("hole {0} is not inside a region")]
+    Uncovered(usize),
+    /// An outline cannot enclose an area, given as the index of the outline
+    #[display("outline {0} has fewer than four vertices")]
+    TooFewVertices(usize),
+}
+
+/// Checks that the outlines of the plan describe a valid floor.
+///
+/// Consecutive vertices of each outline (including the last and the first) must form
+/// axis-aligned edges, no two vertices of an outline may be equal and edges may only touch their
+/// neighbours at shared vertices. If all outlines are valid on their own, regions must not overlap
+/// each other, holes must not overlap each other and every hole must lie within the regions.
+///
+/// # Errors
+///
+/// All defects found, ordered by the indices of the vertices or outlines involved.
+pub fn validate(plan: &FloorPlan) -> Result<(), Vec<Defect>> {
+    let mut defects = vec![];
+    for (idx, (_, range)) in plan.outlines.iter().enumerate() {
+        // closed axis-aligned polygons have at least four corners
+        if range.len() < 4 {
+            defects.push(Defect::TooFewVertices(idx));
+            continue;
+        }
+
+        validate_outline(&plan.vertices, range.clone(), &mut defects);
+    }
+
+    if defects.is_empty() {
+        validate_layout(plan, &mut defects);
+    }
+
+    if defects.is_empty() {
+        return Ok(());
+    }
+
+    defects.sort_unstable_by_key(|defect| match *defect {
+        Defect::Duplicate(l, r) | Defect::Diagonal(l, r) | Defect::Overlap(l, r) => (l, r, 0, 0),
+        Defect::Intersection(a1, a2, b1, b2) => (a1, a2, b1, b2),
+        Defect::Uncovered(idx) | Defect::TooFewVertices(idx) => (idx, 0, 0, 0),
+    });
+    Err(defects)
+}
+
+fn validate_layout(plan: &FloorPlan, defects: &mut Vec<Defect>) {
+    let (xs, ys) = Floor::coordinates(&plan.vertices);
+    let empty = Matrix::new(ys.len().saturating_sub(1), xs.len().saturating_sub(1), None);
+
+    // outline covering each interval cell, per kind
+    let mut owners = [empty.clone(), empty];
+    let mut overlaps = BTreeSet::new();
+    for (idx, (kind, outline)) in plan.outlines().enumerate() {
+        let owner = &mut owners[usize::from(kind == Kind::Hole)];
+        let cells = Floor::interior([outline], &xs, &ys);
+        for pos in cells.keys().filter(|&pos| cells[pos]) {
+            match owner[pos] {
+                Some(other) => {
+                    overlaps.insert((other, idx));
+                }
+                None => owner[pos] = Some(idx),
+            }
+        }
+    }
+
+    let [regions, holes] = owners;
+    let uncovered = holes
+        .keys()
+        .filter(|&pos| regions[pos].is_none())
+        .filter_map(|pos| holes[pos])
+        .collect::<BTreeSet<_>>();
 
-    coords
-        .iter()
-        .combinations(2)
-        .par_bridge()
-        .filter(|it| {
-            let rect = Rect::new(*it[0], *it[1]);
-            poly.contains(&rect)
+    defects.extend(overlaps.into_iter().map(|(l, r)| Defect::Overlap(l, r)));
+    defects.extend(uncovered.into_iter().map(Defect::Uncovered));
+}
+
+fn validate_outline(inp: &[(isize, isize)], range: Range<usize>, defects: &mut Vec<Defect>) {
+    let duplicates = range
+        .clone()
+        .map(|idx| (inp[idx], idx))
+        .into_group_map()
+        .into_values()
+        .flat_map(|indices| {
+            let first = indices.iter().copied().min().unwrap_or_default();
+            indices
+                .into_iter()
+                .filter(move |&idx| idx != first)
+                .map(move |idx| Defect::Duplicate(first, idx))
         })
-        .map(|it| {
-            let Coord { x: x1, y: y1 } = it[0];
-            let Coord { x: x2, y: y2 } = it[1];
-            area((*x1 as isize, *y1 as isize), (*x2 as isize, *y2 as isize))
+        .collect_vec();
+    defects.extend(duplicates);
+
+    let edges = range
+        .clone()
+        .map(|idx| (idx, range.start + (idx + 1 - range.start) % range.len()))
+        .filter(|&(from, to)| {
+            let ((fx, fy), (tx, ty)) = (inp[from], inp[to]);
+            let aligned = fx == tx || fy == ty;
+            if !aligned {
+                defects.push(Defect::Diagonal(from, to));
+            }
+            aligned
         })
-        .max()
+        .collect_vec();
+
+    for (&(a1, a2), &(b1, b2)) in edges.iter().tuple_combinations() {
+        // axis-aligned edges are their own bounding boxes
+        let (a, b) = (
+            Rectangle::new(inp[a1], inp[a2]),
+            Rectangle::new(inp[b1], inp[b2]),
+        );
+        let common_min = (a.min.0.max(b.min.0), a.min.1.max(b.min.1));
+        let common_max = (a.max.0.min(b.max.0), a.max.1.min(b.max.1));
+        if common_min.0 > common_max.0 || common_min.1 > common_max.1 {
+            continue;
+        }
+
+        // neighbouring edges may only share their common vertex
+        let shared = [a1, a2]
+            .into_iter()
+            .filter(|idx| [b1, b2].contains(idx))
+            .map(|idx| inp[idx])
+            .collect_vec();
+        if common_min == common_max && shared.contains(&common_min) {
+            continue;
+        }
+
+        defects.push(Defect::Intersection(a1, a2, b1, b2));
+    }
+}
+
+/// The usable area of a floor plan on a compressed grid.
+///
+/// Even indices stand for the distinct coordinates of the vertices and odd indices for the open
+/// intervals between them. Every element of the grid is thus either entirely usable or not at all:
+/// the boundaries of regions are usable, those of holes are blocked like their inside. The prefix
+/// sums count the usable elements so that any rectangle between two coordinates is checked in
+/// constant time.
+struct Floor {
+    xs: Vec<isize>,
+    ys: Vec<isize>,
+    /// Number of inside elements above and left of each position, with a leading row and column
+    inside_before: Matrix<u32>,
+}
+
+impl Floor {
+    fn new(plan: &FloorPlan) -> Self {
+        let (xs, ys) = Self::coordinates(plan.vertices());
+
+        let of_kind = |kind| {
+            plan.outlines()
+                .filter(move |&(k, _)| k == kind)
+                .map(|(_, outline)| outline)
+        };
+        let regions = Self::interior(of_kind(Kind::Region), &xs, &ys);
+        let holes = Self::interior(of_kind(Kind::Hole), &xs, &ys);
+
+        // coordinates belong to a region or hole if any adjacent interval cell does
+        let adjacent = |idx: usize, len: usize| {
+            if idx % 2 == 1 {
+                idx / 2..idx / 2 + 1
+            } else {
+                (idx / 2).saturating_sub(1)..(idx / 2 + 1).min(len)
+            }
+        };
+
+        let (rows, columns) = (
+            (2 * ys.len()).saturating_sub(1),
+            (2 * xs.len()).saturating_sub(1),
+        );
+        let mut inside_before = Matrix::new(rows + 1, columns + 1, 0);
+        for row in 0..rows {
+            for col in 0..columns {
+                let touches = |cells: &Matrix<bool>| {
+                    adjacent(row, cells.rows)
+                        .cartesian_product(adjacent(col, cells.columns))
+                        .any(|pos| cells[pos])
+                };
+                let inside = touches(&regions) && !touches(&holes);
+
+                inside_before[(row + 1, col + 1)] = u32::from(inside)
+                    + inside_before[(row, col + 1)]
+                    + inside_before[(row + 1, col)]
+                    - inside_before[(row, col)];
+            }
+        }
+
+        Self {
+            xs,
+            ys,
+            inside_before,
+        }
+    }
+
+    /// Distinct x and y coordinates of the vertices, in ascending order.
+    fn coordinates(vertices: &[(isize, isize)]) -> (Vec<isize>, Vec<isize>) {
+        let xs = vertices
+            .iter()
+            .map(|&(x, _)| x)
+            .sorted()
+            .dedup()
+            .collect_vec();
+        let ys = vertices
+            .iter()
+            .map(|&(_, y)| y)
+            .sorted()
+            .dedup()
+            .collect_vec();
+
+        (xs, ys)
+    }
+
+    /// Interval cells inside an odd number of the outlines, i.e. inside their union as long as
+    /// they do not overlap.
+    fn interior<'a>(
+        outlines: impl IntoIterator<Item = &'a [(isize, isize)]>,
+        xs: &[isize],
+        ys: &[isize],
+    ) -> Matrix<bool> {
+        // interval cells are inside if an odd number of vertical edges lies left of them
+        let mut crossings = Matrix::new(ys.len().saturating_sub(1), xs.len(), false);
+        for outline in outlines {
+            for (&(x, y1), &(_, y2)) in outline.iter().circular_tuple_windows() {
+                let col = Self::index(xs, x);
+                let (y1, y2) = (Self::index(ys, y1.min(y2)), Self::index(ys, y1.max(y2)));
+                for row in y1..y2 {
+                    crossings[(row, col)] ^= true;
+                }
+            }
+        }
+
+        let mut cells = Matrix::new(crossings.rows, xs.len().saturating_sub(1), false);
+        for row in 0..cells.rows {
+            let mut inside = false;
+            for col in 0..cells.columns {
+                inside ^= crossings[(row, col)];
+                cells[(row, col)] = inside;
+            }
+        }
+
+        cells
+    }
+
+    fn index(coords: &[isize], coord: isize) -> usize {
+        coords.binary_search(&coord).unwrap_or_else(|idx| idx)
+    }
+
+    /// Whether the rectangle spanned by two vertices lies entirely within the area.
+    fn contains(&self, (lx, ly): (isize, isize), (rx, ry): (isize, isize)) -> bool {
+        let (x1, x2) = (
+            Self::index(&self.xs, lx.min(rx)),
+            Self::index(&self.xs, lx.max(rx)),
+        );
+        let (y1, y2) = (
+            Self::index(&self.ys, ly.min(ry)),
+            Self::index(&self.ys, ly.max(ry)),
+        );
+        let (top, left, bottom, right) = (2 * y1, 2 * x1, 2 * y2 + 1, 2 * x2 + 1);
+
+        let inside = self.inside_before[(bottom, right)] + self.inside_before[(top, left)]
+            - self.inside_before[(top, right)]
+            - self.inside_before[(bottom, left)];
+
+        inside as usize == (bottom - top) * (right - left)
+    }
 }
 
 #[cfg(test)]
@@ -86,4 +502,181 @@ mod tests {
         let res = part2(&data);
         assert_eq!(res, Some(24));
     }
+
+    #[test]
+    fn test_placement() {
+        let data = generate(TEST_INPUT).expect("valid input");
+
+        let res = largest_rectangles(&data).expect("rectangles");
+        assert_eq!(res.area, 50);
+        assert_eq!(
+            res.rectangles,
+            [
+                Rectangle {
+                    corners: [(11, 1), (2, 5)],
+                    min: (2, 1),
+                    max: (11, 5),
+                },
+                Rectangle {
+                    corners: [(11, 7), (2, 3)],
+                    min: (2, 3),
+                    max: (11, 7),
+                }
+            ]
+        );
+
+        let res = largest_enclosed_rectangles(&data).expect("rectangles");
+        assert_eq!(res.area, 24);
+        assert_eq!(
+            res.rectangles,
+            [Rectangle {
+                corners: [(9, 5), (2, 3)],
+                min: (2, 3),
+                max: (9, 5),
+            }]
+        );
+
+        // both diagonals of a square span the same rectangle
+        let data = generate("0,0\n4,0\n4,4\n0,4").expect("valid input");
+        let res = largest_enclosed_rectangles(&data).expect("rectangles");
+        assert_eq!(res.area, 25);
+        assert_eq!(res.rectangles.len(), 2);
+        assert!(
+            res.rectangles
+                .iter()
+                .all(|r| (r.min, r.max) == ((0, 0), (4, 4)))
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let data = generate(TEST_INPUT).expect("valid input");
+        assert_eq!(validate(&data), Ok(()));
+
+        let data = generate("0,0\n4,0\n4,4\n1,3\n0,4").expect("valid input");
+        assert_eq!(
+            validate(&data),
+            Err(vec![Defect::Diagonal(2, 3), Defect::Diagonal(3, 4)])
+        );
+        assert_eq!(largest_enclosed_rectangles(&data), None);
+
+        let data = generate("0,0\n4,0\n4,4\n2,4\n2,-2\n0,-2").expect("valid input");
+        let defects = validate(&data).expect_err("self-intersecting");
+        assert_eq!(defects, [Defect::Intersection(0, 1, 3, 4)]);
+        assert_eq!(defects[0].to_string(), "edges 0-1 and 3-4 intersect");
+
+        // doubling back along an edge overlaps its neighbour
+        let data = generate("0,0\n4,0\n2,0\n2,2\n0,2").expect("valid input");
+        assert_eq!(
+            validate(&data),
+            Err(vec![
+                Defect::Intersection(0, 1, 1, 2),
+                Defect::Intersection(0, 1, 2, 3)
+            ])
+        );
+
+        let data = generate("0,0\n4,0\n4,2\n4,0\n4,4\n0,4").expect("valid input");
+        let defects = validate(&data).expect_err("duplicate vertex");
+        assert!(defects.contains(&Defect::Duplicate(1, 3)));
+
+        // outlines that cannot enclose anything
+        let data = generate("5,5").expect("valid input");
+        assert_eq!(validate(&data), Err(vec![Defect::TooFewVertices(0)]));
+        let data = generate("0,0\n4,0\n4,4\n0,4\n\nkind: hole").expect("valid input");
+        assert_eq!(validate(&data), Err(vec![Defect::TooFewVertices(1)]));
+
+        // trailing blank lines do not start another outline
+        let data = generate(&format!("{TEST_INPUT}\n\n\n")).expect("valid input");
+        assert_eq!(data.outlines().count(), 1);
+        assert_eq!(validate(&data), Ok(()));
+    }
+
+    #[test]
+    fn test_large_coordinates() {
+        // the test input scaled beyond the precision of f64, shifted by one
+        let scale = 1 << 60;
+        let vertices = generate(TEST_INPUT)
+            .expect("valid input")
+            .vertices()
+            .iter()
+            .map(|&(x, y)| (x * (scale / 16) + 1, y * (scale / 16) + 1))
+            .collect_vec();
+        let data = FloorPlan::new([(Kind::Region, vertices)]);
+
+        let width = 7 * (scale / 16).cast_unsigned() as u128 + 1;
+        let height = 2 * (scale / 16).cast_unsigned() as u128 + 1;
+        assert_eq!(part2(&data), Some(width * height));
+
+        // a U shape: the rectangle across the gap between both arms is outside
+        let data = generate("0,0\n3,0\n3,3\n2,3\n2,1\n1,1\n1,3\n0,3").expect("valid input");
+        assert_eq!(part2(&data), Some(8));
+    }
+
+    #[test]
+    fn test_holes() {
+        let plan = "0,0\n10,0\n10,10\n0,10\n\n\
+                    kind: hole\n4,4\n6,4\n6,6\n4,6\n\n\
+                    kind: region\n20,0\n22,0\n22,3\n20,3";
+        let data = generate(plan).expect("valid input");
+        assert_eq!(
+            data.outlines()
+                .map(|(kind, o)| (kind, o.len()))
+                .collect_vec(),
+            [(Kind::Region, 4), (Kind::Hole, 4), (Kind::Region, 4)]
+        );
+        assert_eq!(validate(&data), Ok(()));
+
+        // the hole blocks the whole floor but its outer strips, and the gap between the regions
+        // cannot be spanned
+        let res = largest_enclosed_rectangles(&data).expect("rectangles");
+        assert_eq!(res.area, 12);
+        assert_eq!(res.rectangles.len(), 2);
+        assert!(
+            res.rectangles
+                .iter()
+                .all(|r| (r.min, r.max) == ((20, 0), (22, 3)))
+        );
+        assert_eq!(largest_rectangles(&data).map(|res| res.area), Some(23 * 11));
+
+        let data = generate("0,0\n1,0\n1,1\n0,1\n\nkind: region\n5,0\n7,0\n7,2\n5,2")
+            .expect("valid input");
+        assert_eq!(part2(&data), Some(9));
+
+        // indices of defects refer to the whole input
+        let data =
+            generate("0,0\n1,0\n1,1\n0,1\n\nkind: hole\n5,0\n7,0\n7,2\n6,3").expect("valid input");
+        assert_eq!(
+            validate(&data),
+            Err(vec![Defect::Diagonal(6, 7), Defect::Diagonal(7, 4)])
+        );
+
+        assert!(generate("kind: pillar\n0,0").is_none());
+
+        // overlapping regions, overlapping holes and a hole sticking out of the regions
+        let plan = "0,0\n4,0\n4,4\n0,4\n\n\
+                    2,2\n6,2\n6,6\n2,6\n\n\
+                    kind: hole\n1,1\n2,1\n2,2\n1,2\n\n\
+                    kind: hole\n1,1\n3,1\n3,3\n1,3\n\n\
+                    kind: hole\n5,5\n8,5\n8,8\n5,8";
+        let data = generate(plan).expect("valid input");
+        let defects = validate(&data).expect_err("overlapping outlines");
+        assert_eq!(
+            defects,
+            [
+                Defect::Overlap(0, 1),
+                Defect::Overlap(2, 3),
+                Defect::Uncovered(4)
+            ]
+        );
+        assert_eq!(defects[2].to_string(), "hole 4 is not inside a region");
+        assert_eq!(part2(&data), None);
+
+        // regions may share a boundary and holes may touch the boundary of their region, the
+        // tiles on the outline of the hole are blocked, so the best rectangle starts at x = 2
+        let data =
+            generate("0,0\n2,0\n2,2\n0,2\n\n2,0\n4,0\n4,2\n2,2\n\nkind: hole\n0,0\n1,0\n1,1\n0,1")
+                .expect("valid input");
+        assert_eq!(validate(&data), Ok(()));
+        assert_eq!(part2(&data), Some(9));
+    }
 }
@@ -10,14 +10,17 @@
 mod day01;
 mod day02;
 mod day03;
-mod day04;
-mod day05;
-mod day06;
-mod day07;
-mod day08;
-mod day09;
+pub mod day04;
+pub mod day05;
+pub mod day06;
+pub mod day07;
+pub mod day08;
+pub mod day09;
 mod day10;
 mod day11;
 mod day12;
 
+pub mod range_set;
+pub mod union_find;
+
 aoc_runner_derive::aoc_lib! { year = 2025 }
@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+
+/// Set of `u64` values stored as sorted, disjoint and non-adjacent inclusive intervals.
+/// The whole domain up to `u64::MAX` is supported without overflowing.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RangeSet {
+    // start -> end (inclusive)
+    ranges: BTreeMap<u64, u64>,
+}
+
+impl RangeSet {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            ranges: BTreeMap::new(),
+        }
+    }
+
+    /// Adds all values of `from..=to`, merging with overlapping or adjacent intervals.
+    /// Reversed intervals are empty and leave the set unchanged.
+    pub fn insert(&mut self, from: u64, to: u64) {
+        if from > to {
+            return;
+        }
+
+        let (mut from, mut to) = (from, to);
+
+        while let Some((&start, &end)) = self.ranges.range(..=to.saturating_add(1)).next_back() {
+            if end.saturating_add(1) < from {
+                break;
+            }
+
+            self.ranges.remove(&start);
+            from = from.min(start);
+            to = to.max(end);
+        }
+
+        self.ranges.insert(from, to);
+    }
+
+    /// Removes all values of `from..=to`, splitting intervals that are only partially covered.
+    pub fn remove(&mut self, from: u64, to: u64) {
+        if from > to {
+            return;
+        }
+
+        while let Some((&start, &end)) = self.ranges.range(..=to).next_back() {
+            if end < from {
+                break;
+            }
+
+            self.ranges.remove(&start);
+
+            if end > to {
+                self.ranges.insert(to + 1, end);
+            }
+
+            if start < from {
+                self.ranges.insert(start, from - 1);
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn contains(&self, value: u64) -> bool {
+        self.ranges
+            .range(..=value)
+            .next_back()
+            .is_some_and(|(_, &end)| end >= value)
+    }
+
+    #[must_use]
+    pub fn union(&self, other: &Self) -> Self {
+        let mut res = self.clone();
+        for (from, to) in other.iter() {
+            res.insert(from, to);
+        }
+
+        res
+    }
+
+    #[must_use]
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut res = Self::new();
+
+        let mut lhs = self.iter().peekable();
+        let mut rhs = other.iter().peekable();
+
+        while let (Some(&(lfrom, lto)), Some(&(rfrom, rto))) = (lhs.peek(), rhs.peek()) {
+            let (from, to) = (lfrom.max(rfrom), lto.min(rto));
+            if from <= to {
+                res.ranges.insert(from, to);
+            }
+
+            if lto < rto {
+                lhs.next();
+            } else {
+                rhs.next();
+            }
+        }
+
+        res
+    }
+
+    #[must_use]
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut res = self.clone();
+        for (from, to) in other.iter() {
+            res.remove(from, to);
+        }
+
+        res
+    }
+
+    /// All values of `lower..=upper` that are not part of the set.
+    #[must_use]
+    pub fn complement(&self, lower: u64, upper: u64) -> Self {
+        let mut res = Self::new();
+        res.insert(lower, upper);
+
+        res.difference(self)
+    }
+
+    /// Number of values covered by the set, which exceeds `u64` if the whole domain is covered.
+    #[must_use]
+    pub fn len(&self) -> u128 {
+        self.iter()
+            .map(|(from, to)| u128::from(to - from) + 1)
+            .sum()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// The disjoint intervals in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.ranges.iter().map(|(&from, &to)| (from, to))
+    }
+}
+
+impl FromIterator<(u64, u64)> for RangeSet {
+    fn from_iter<T: IntoIterator<Item = (u64, u64)>>(iter: T) -> Self {
+        let mut res = Self::new();
+        for (from, to) in iter {
+            res.insert(from, to);
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+
+    #[test]
+    fn test_insert_merges() {
+        let set = RangeSet::from_iter([(3, 5), (10, 14), (16, 20), (12, 18), (6, 6)]);
+        assert_eq!(set.iter().collect_vec(), vec![(3, 6), (10, 20)]);
+        assert_eq!(set.len(), 15);
+        assert!(set.contains(6) && set.contains(17));
+        assert!(!set.contains(2) && !set.contains(7) && !set.contains(21));
+    }
+
+    #[test]
+    fn test_remove_splits() {
+        let mut set = RangeSet::from_iter([(0, 10), (20, 30)]);
+        set.remove(5, 22);
+        assert_eq!(set.iter().collect_vec(), vec![(0, 4), (23, 30)]);
+
+        set.remove(0, 0);
+        set.remove(30, u64::MAX);
+        assert_eq!(set.iter().collect_vec(), vec![(1, 4), (23, 29)]);
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let lhs = RangeSet::from_iter([(0, 10), (20, 30)]);
+        let rhs = RangeSet::from_iter([(5, 25), (40, 50)]);
+
+        assert_eq!(
+            lhs.union(&rhs).iter().collect_vec(),
+            vec![(0, 30), (40, 50)]
+        );
+        assert_eq!(
+            lhs.intersection(&rhs).iter().collect_vec(),
+            vec![(5, 10), (20, 25)]
+        );
+        assert_eq!(
+            lhs.difference(&rhs).iter().collect_vec(),
+            vec![(0, 4), (26, 30)]
+        );
+        assert_eq!(
+            lhs.complement(5, 45).iter().collect_vec(),
+            vec![(11, 19), (31, 45)]
+        );
+    }
+
+    #[test]
+    fn test_full_domain() {
+        let mut set = RangeSet::from_iter([(0, 10), (u64::MAX - 10, u64::MAX)]);
+        assert_eq!(set.len(), 22);
+        assert!(set.contains(u64::MAX));
+
+        set.insert(11, u64::MAX - 11);
+        assert_eq!(set.iter().collect_vec(), vec![(0, u64::MAX)]);
+        assert_eq!(set.len(), 1 << 64);
+        assert!(set.complement(0, u64::MAX).is_empty());
+    }
+}
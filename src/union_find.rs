@@ -0,0 +1,102 @@
+/// Disjoint-set forest over the elements `0..n` with path compression and union by size.
+#[derive(Clone, Debug)]
+pub struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+    num_components: usize,
+}
+
+impl UnionFind {
+    #[must_use]
+    pub fn new(n: usize) -> Self {
+        Self {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+            num_components: n,
+        }
+    }
+
+    /// Representative of the component containing `x`.
+    pub fn find(&mut self, x: usize) -> usize {
+        let mut root = x;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+
+        let mut x = x;
+        while self.parent[x] != root {
+            let next = self.parent[x];
+            self.parent[x] = root;
+            x = next;
+        }
+
+        root
+    }
+
+    /// Merges the components of `a` and `b`, returns `false` if they were already connected.
+    pub fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+
+        let (large, small) = if self.size[a] >= self.size[b] {
+            (a, b)
+        } else {
+            (b, a)
+        };
+
+        self.parent[small] = large;
+        self.size[large] += self.size[small];
+        self.num_components -= 1;
+
+        true
+    }
+
+    pub fn connected(&mut self, a: usize, b: usize) -> bool {
+        self.find(a) == self.find(b)
+    }
+
+    /// Size of the component containing `x`.
+    pub fn component_size(&mut self, x: usize) -> usize {
+        let root = self.find(x);
+        self.size[root]
+    }
+
+    #[must_use]
+    pub const fn num_components(&self) -> usize {
+        self.num_components
+    }
+
+    /// Sizes of all components, in no particular order.
+    pub fn component_sizes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.parent
+            .iter()
+            .enumerate()
+            .filter(|&(x, &parent)| x == parent)
+            .map(|(x, _)| self.size[x])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+
+    #[test]
+    fn test_union_find() {
+        let mut uf = UnionFind::new(6);
+        assert_eq!(uf.num_components(), 6);
+
+        assert!(uf.union(0, 1));
+        assert!(uf.union(2, 3));
+        assert!(uf.union(1, 3));
+        assert!(!uf.union(0, 2));
+
+        assert!(uf.connected(0, 3));
+        assert!(!uf.connected(0, 4));
+        assert_eq!(uf.component_size(2), 4);
+        assert_eq!(uf.num_components(), 3);
+        assert_eq!(uf.component_sizes().sorted().collect_vec(), [1, 1, 4]);
+    }
+}